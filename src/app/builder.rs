@@ -1,5 +1,10 @@
 /// Module with functions to build fltk widgets
 
+use std::{
+    cell::Cell,
+    rc::Rc
+};
+
 use fltk::{
     app::{
         App as FLTKApp,
@@ -13,6 +18,10 @@ use fltk::{
         Button,
         CheckButton
     },
+    browser::{
+        CheckBrowser,
+        HoldBrowser
+    },
     draw,
     enums::{
         Align,
@@ -26,6 +35,7 @@ use fltk::{
         PackType
     },
     image,
+    menu::Choice,
     text::{
         TextBuffer,
         TextDisplay,
@@ -39,7 +49,9 @@ use fltk::{
         DisplayExt,
         ButtonExt,
         ValuatorExt,
-        ImageExt
+        ImageExt,
+        MenuExt,
+        BrowserExt
     },
     misc::Progress,
     valuator::Slider,
@@ -664,13 +676,24 @@ pub fn build_license_win(sender: Sender<Message>, app_state: &ThreadSafeState) -
 
 
 /// Builds the select directory window
-pub fn build_select_dir_win(sender: Sender<Message>, app_state: &ThreadSafeState, txt_buf: TextBuffer) -> DoubleWindow {
-    let select_dir_win = build_inner_win();
+pub fn build_select_dir_win(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState,
+    txt_buf: TextBuffer,
+    info_frame: &Frame,
+    report_frame: &Frame,
+    found_dirs_choice: &Choice
+) -> DoubleWindow {
+    let mut select_dir_win = build_inner_win();
     select_dir_win.begin();
 
 
     _build_top_frame(SELECT_DIR_FRAME_LABEL);
 
+    let mut found_dirs_choice = found_dirs_choice.clone();
+    found_dirs_choice.set_pos(SEL_DIR_TXT_XPOS+FOUND_DIRS_CHOICE_XOFFSET, SEL_DIR_TXT_YPOS-FOUND_DIRS_CHOICE_HEIGHT-BUT_SPACING);
+    select_dir_win.add(&found_dirs_choice);
+
     let mut txt = TextDisplay::default()
         .with_size(SEL_DIR_TXT_WIDTH, SEL_DIR_TXT_HEIGHT)
         .with_pos(SEL_DIR_TXT_XPOS, SEL_DIR_TXT_YPOS);
@@ -684,6 +707,18 @@ pub fn build_select_dir_win(sender: Sender<Message>, app_state: &ThreadSafeState
     let mut but = build_button(BUT_SELECT_DIR_LABEL, sender, Message::SelectDir);
     but.set_pos(INNER_WIN_CONTENT_XPADDING+SEL_DIR_TXT_WIDTH-BUT_WIDTH, SEL_DIR_TXT_YPOS+SEL_DIR_TXT_HEIGHT+BUT_SPACING/2);
 
+    let mut but = build_button(BUT_UNINSTALL_LABEL, sender, Message::Uninstall);
+    but.set_pos(INNER_WIN_CONTENT_XPADDING, SEL_DIR_TXT_YPOS+SEL_DIR_TXT_HEIGHT+BUT_SPACING/2);
+
+    let mut but = build_button(BUT_VERIFY_LABEL, sender, Message::Verify);
+    but.set_pos(INNER_WIN_CONTENT_XPADDING+BUT_WIDTH+BUT_SPACING, SEL_DIR_TXT_YPOS+SEL_DIR_TXT_HEIGHT+BUT_SPACING/2);
+
+    let mut but = build_button(BUT_RESTORE_LABEL, sender, Message::Restore);
+    but.set_pos(INNER_WIN_CONTENT_XPADDING+2*(BUT_WIDTH+BUT_SPACING), SEL_DIR_TXT_YPOS+SEL_DIR_TXT_HEIGHT+BUT_SPACING/2);
+
+    select_dir_win.add(info_frame);
+    select_dir_win.add(report_frame);
+
     _build_abort_back_contn_pack(sender, app_state);
 
 
@@ -693,16 +728,109 @@ pub fn build_select_dir_win(sender: Sender<Message>, app_state: &ThreadSafeState
 }
 
 
+/// Builds a dropdown with the DDLC installations we found,
+/// it's inactive until the search is done
+pub fn build_found_dirs_choice(sender: Sender<Message>) -> Choice {
+    let mut choice = Choice::default()
+        .with_size(SEL_DIR_TXT_WIDTH-FOUND_DIRS_CHOICE_XOFFSET, FOUND_DIRS_CHOICE_HEIGHT)
+        .with_label(FOUND_DIRS_CHOICE_LABEL);
+    choice.set_label_font(BUT_FONT);
+    choice.set_label_size(BUT_FONT_SIZE);
+    choice.set_text_font(BUT_FONT);
+    choice.set_text_size(BUT_FONT_SIZE);
+    choice.set_color(C_DDLC_WHITE_ACT);
+    choice.set_selection_color(C_DDLC_PINK_DARK);
+    choice.set_down_frame(FrameType::FlatBox);
+    choice.add_choice(FOUND_DIRS_CHOICE_SEARCHING_LABEL);
+    choice.set_value(0);
+    choice.deactivate();
+    choice.emit(sender, Message::SelectFoundDir);
+
+    return choice;
+}
+
+/// Builds a frame to show what happened to the files after an abort
+pub fn build_abort_info_frame() -> Frame {
+    return _build_mid_frame(ABORT_MID_FRAME_LABEL);
+}
+
+/// Builds a frame to show the installed and the latest versions of MAS
+pub fn build_install_info_frame() -> Frame {
+    let mut frame = Frame::default()
+        .with_size(SEL_DIR_TXT_WIDTH, INSTALL_INFO_FRAME_HEIGHT)
+        .with_pos(SEL_DIR_TXT_XPOS, INSTALL_INFO_FRAME_YPOS);
+    frame.set_align(Align::Center | Align::Inside);
+    frame.set_label_color(C_DDLC_PINK_DARK);
+    frame.set_label_size(INSTALL_INFO_FRAME_LABEL_SIZE);
+
+    return frame;
+}
+
+
+/// Builds a frame to show the checklist of the selected DDLC directory
+pub fn build_dir_report_frame() -> Frame {
+    let mut frame = Frame::default()
+        .with_size(SEL_DIR_TXT_WIDTH, DIR_REPORT_FRAME_HEIGHT)
+        .with_pos(SEL_DIR_TXT_XPOS, DIR_REPORT_FRAME_YPOS);
+    frame.set_align(Align::Center | Align::Inside);
+    frame.set_label_color(C_DDLC_PINK_DARK);
+    frame.set_label_size(DIR_REPORT_FRAME_LABEL_SIZE);
+
+    return frame;
+}
+
+
+/// Builds a check button to select a local archive for offline installation
+/// The button is checked while an archive is selected
+pub fn build_local_archive_check_button(label: &str, sender: Sender<Message>, msg: Message) -> CheckButton {
+    return _build_check_button(
+        BUT_LOCAL_ARCHIVE_CHECK_WIDTH,
+        BUT_LOCAL_ARCHIVE_CHECK_HEIGHT,
+        label,
+        sender,
+        msg,
+        false
+    );
+}
+
+/// Builds a dropdown to select the release to install
+/// The first item is the latest release, the rest are added once we fetch them
+pub fn build_release_choice(sender: Sender<Message>) -> Choice {
+    let mut choice = Choice::default()
+        .with_size(RELEASE_CHOICE_WIDTH, RELEASE_CHOICE_HEIGHT)
+        .with_label(RELEASE_CHOICE_LABEL);
+    choice.set_label_font(BUT_FONT);
+    choice.set_label_size(BUT_FONT_SIZE);
+    choice.set_text_font(BUT_FONT);
+    choice.set_text_size(BUT_FONT_SIZE);
+    choice.set_color(C_DDLC_WHITE_ACT);
+    choice.set_selection_color(C_DDLC_PINK_DARK);
+    choice.set_down_frame(FrameType::FlatBox);
+    choice.add_choice(RELEASE_CHOICE_LATEST_LABEL);
+    choice.set_value(0);
+    choice.emit(sender, Message::SelectRelease);
+
+    return choice;
+}
+
 /// Builds the options window with various settings for installer
-pub fn build_options_win(sender: Sender<Message>, app_state: &ThreadSafeState, is_dlx_version: bool, install_spr: bool) -> DoubleWindow {
-    let options_win = build_inner_win();
+pub fn build_options_win(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState,
+    is_dlx_version: bool,
+    install_spr: bool,
+    spr_into_game: bool,
+    release_choice: &Choice,
+    local_archive_buts: [&CheckButton; 2]
+) -> DoubleWindow {
+    let mut options_win = build_inner_win();
     options_win.begin();
 
 
     _build_top_frame(OPTIONS_FRAME_LABEL);
 
 
-    const TOTAL_BUTS: i32 = 2;
+    const TOTAL_BUTS: i32 = 6;
     const XPOS: i32 = INNER_WIN_CONTENT_XPADDING;
     const YPOS: i32 = INNER_WIN_HEIGHT/2 - TOTAL_BUTS*BUT_HEIGHT/2 - (TOTAL_BUTS-1)*BUT_SPACING/2;
     const YPOS_INC: i32 = BUT_HEIGHT + BUT_SPACING;
@@ -725,6 +853,25 @@ pub fn build_options_win(sender: Sender<Message>, app_state: &ThreadSafeState, i
         install_spr
     );
     but_inst_spr.set_pos(XPOS, YPOS+YPOS_INC);
+    let mut but_spr_into_game = _build_check_button(
+        BUT_SPR_INTO_GAME_CHECK_WIDTH,
+        BUT_SPR_INTO_GAME_CHECK_HEIGHT,
+        BUT_SPR_INTO_GAME_CHECK_LABEL,
+        sender,
+        Message::SprIntoGameCheck,
+        spr_into_game
+    );
+    but_spr_into_game.set_pos(XPOS, YPOS+2*YPOS_INC);
+
+    let mut release_choice = release_choice.clone();
+    release_choice.set_pos(XPOS+RELEASE_CHOICE_XOFFSET, YPOS+3*YPOS_INC);
+    options_win.add(&release_choice);
+
+    for (i, but) in local_archive_buts.into_iter().enumerate() {
+        let mut but = but.clone();
+        but.set_pos(XPOS, YPOS+(4+i as i32)*YPOS_INC);
+        options_win.add(&but);
+    }
 
 
     _build_abort_back_inst_pack(sender, app_state);
@@ -750,8 +897,26 @@ pub fn build_progress_bar() -> Progress {
     return bar;
 }
 
+/// Builds a frame to show what we're doing under the progress bar,
+/// e.g. the file being extracted
+pub fn build_progress_info_frame() -> Frame {
+    let mut frame = Frame::default()
+        .with_size(PB_WIDTH, PROGRESS_INFO_FRAME_HEIGHT)
+        .with_pos(INNER_WIN_CONTENT_XPADDING, WIN_HEIGHT/2+PB_HEIGHT/2+BUT_SPACING);
+    frame.set_align(Align::Left | Align::Inside | Align::Clip);
+    frame.set_label_color(C_DDLC_PINK_DARK);
+    frame.set_label_size(PROGRESS_INFO_FRAME_LABEL_SIZE);
+
+    return frame;
+}
+
 /// Builds the downloading/installing window
-pub fn build_propgress_win(sender: Sender<Message>, app_state: &ThreadSafeState, bar: &Progress) -> DoubleWindow {
+pub fn build_propgress_win(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState,
+    bar: &Progress,
+    info_frame: &Frame
+) -> DoubleWindow {
     let mut progress_win = build_inner_win();
     progress_win.begin();
 
@@ -768,6 +933,7 @@ pub fn build_propgress_win(sender: Sender<Message>, app_state: &ThreadSafeState,
     pack.end();
 
     progress_win.add(bar);
+    progress_win.add(info_frame);
 
 
     progress_win.end();
@@ -776,6 +942,48 @@ pub fn build_propgress_win(sender: Sender<Message>, app_state: &ThreadSafeState,
 }
 
 
+/// Builds a list of check buttons to select spritepacks,
+/// the items are added once we know the spritepacks
+pub fn build_spritepacks_browser() -> CheckBrowser {
+    let mut browser = CheckBrowser::default()
+        .with_size(TXT_DISP_WIDTH, TXT_DISP_HEIGHT)
+        .with_pos(TXT_DISP_XPOS, TXT_DISP_YPOS);
+    browser.set_text_size(SPR_BROWSER_TXT_SIZE);
+    browser.set_frame(FrameType::EngravedFrame);
+    browser.set_selection_color(C_DDLC_PINK_IDLE);
+
+    return browser;
+}
+
+/// Builds the window to select spritepacks to install
+pub fn build_spritepacks_win(sender: Sender<Message>, app_state: &ThreadSafeState, browser: &CheckBrowser) -> DoubleWindow {
+    let mut spritepacks_win = build_inner_win();
+    spritepacks_win.begin();
+
+
+    _build_top_frame(SPRITEPACKS_FRAME_LABEL);
+
+    spritepacks_win.add(browser);
+
+    let mut pack = _build_4but_left_inner_pack();
+    pack.set_pos(INNER_WIN_CONTENT_XPADDING, INNER_WIN_HEIGHT-BUT_HEIGHT-BUT_PACK_YPADDING);
+    pack.begin();
+
+    build_button(BUT_ABORT_LABEL, sender, Message::Abort);
+    build_volume_but(sender, app_state);
+
+    pack.end();
+
+    let mut but = build_button(BUT_CONTINUE_LABEL, sender, Message::SpritepacksSelected);
+    but.set_pos(INNER_WIN_WIDTH-INNER_WIN_CONTENT_XPADDING-BUT_WIDTH, INNER_WIN_HEIGHT-BUT_HEIGHT-BUT_PACK_YPADDING);
+
+
+    spritepacks_win.end();
+
+    return spritepacks_win;
+}
+
+
 /// Formats a message to
 /// text:
 ///     more text:
@@ -899,6 +1107,163 @@ pub fn build_msg_win(msg: &str) -> DoubleWindow {
     return alert_win;
 }
 
+/// Builds a window to let the user choose one of the given options
+/// The index of the chosen option is written into the given cell
+pub fn build_choice_win(msg: &str, options: &[&str], choice: Rc<Cell<Option<usize>>>) -> DoubleWindow {
+    let (sw, sh) = screen_size();
+
+    let win_x = sw as i32/2 - CHOICE_WIN_WIDTH/2;
+    let win_y = sh as i32/2 - CHOICE_WIN_HEIGHT/2;
+
+    let mut choice_win = Window::default()
+        .with_size(CHOICE_WIN_WIDTH, CHOICE_WIN_HEIGHT)
+        .with_pos(win_x, win_y)
+        .with_label(CHOICE_WIN_TITLE);
+    choice_win.set_color(C_DDLC_PINK_IDLE);
+
+    let mut inner_win = Window::default()
+        .with_size(INNER_CHOICE_WIN_WIDTH, INNER_CHOICE_WIN_HEIGHT)
+        .with_pos(WIN_PADDING, WIN_PADDING);
+    inner_win.set_color(C_DDLC_WHITE_IDLE);
+
+
+    let mut frame = Frame::default()
+        .with_size(INNER_CHOICE_WIN_WIDTH, INNER_CHOICE_WIN_HEIGHT - BUT_HEIGHT - 2*BUT_CHOICE_WIN_PADDING)
+        .with_pos(0, 0)
+        .with_align(Align::Center | Align::Inside)
+        .with_label(msg);
+    frame.set_label_color(C_DDLC_PINK_DARK);
+    frame.set_label_size(MSG_FRAME_LABEL_SIZE);
+
+    // Center the buttons
+    let total_buts = options.len() as i32;
+    let buts_width = total_buts*BUT_WIDTH + (total_buts-1)*BUT_SPACING;
+    let but_xpos = INNER_CHOICE_WIN_WIDTH/2 - buts_width/2;
+    let but_ypos = INNER_CHOICE_WIN_HEIGHT - BUT_HEIGHT - BUT_CHOICE_WIN_PADDING;
+
+    for (i, label) in options.iter().enumerate() {
+        let mut but = _build_button_base(
+            BUT_WIDTH,
+            BUT_HEIGHT,
+            label,
+            _handle_button,
+            _draw_button
+        );
+        but.set_pos(but_xpos + i as i32*(BUT_WIDTH+BUT_SPACING), but_ypos);
+        but.set_callback({
+            let mut win = choice_win.clone();
+            let choice = choice.clone();
+            move |_| {
+                choice.set(Some(i));
+                win.hide();
+            }
+        });
+    }
+
+
+    inner_win.end();
+
+    choice_win.end();
+    choice_win.hide();
+    choice_win.make_modal(true);
+
+    return choice_win;
+}
+
+
+/// Builds a modal window to pick one of the given items from a list,
+/// the index of the picked item is saved into the cell
+pub fn build_select_item_win(msg: &str, items: &[String], choice: Rc<Cell<Option<usize>>>) -> DoubleWindow {
+    let (sw, sh) = screen_size();
+
+    let win_x = sw as i32/2 - SELECT_ITEM_WIN_WIDTH/2;
+    let win_y = sh as i32/2 - SELECT_ITEM_WIN_HEIGHT/2;
+
+    let mut select_win = Window::default()
+        .with_size(SELECT_ITEM_WIN_WIDTH, SELECT_ITEM_WIN_HEIGHT)
+        .with_pos(win_x, win_y)
+        .with_label(SELECT_ITEM_WIN_TITLE);
+    select_win.set_color(C_DDLC_PINK_IDLE);
+
+    let mut inner_win = Window::default()
+        .with_size(INNER_SELECT_ITEM_WIN_WIDTH, INNER_SELECT_ITEM_WIN_HEIGHT)
+        .with_pos(WIN_PADDING, WIN_PADDING);
+    inner_win.set_color(C_DDLC_WHITE_IDLE);
+
+
+    let mut frame = Frame::default()
+        .with_size(INNER_SELECT_ITEM_WIN_WIDTH, SELECT_ITEM_FRAME_HEIGHT)
+        .with_pos(0, 0)
+        .with_align(Align::Center | Align::Inside)
+        .with_label(msg);
+    frame.set_label_color(C_DDLC_PINK_DARK);
+    frame.set_label_size(MSG_FRAME_LABEL_SIZE);
+
+    let mut browser = HoldBrowser::default()
+        .with_size(
+            INNER_SELECT_ITEM_WIN_WIDTH - 2*BUT_CHOICE_WIN_PADDING,
+            INNER_SELECT_ITEM_WIN_HEIGHT - SELECT_ITEM_FRAME_HEIGHT - BUT_HEIGHT - 2*BUT_CHOICE_WIN_PADDING
+        )
+        .with_pos(BUT_CHOICE_WIN_PADDING, SELECT_ITEM_FRAME_HEIGHT);
+    browser.set_text_size(SELECT_ITEM_TXT_SIZE);
+    browser.set_selection_color(C_DDLC_PINK_IDLE);
+    for item in items {
+        browser.add(item);
+    }
+    if !items.is_empty() {
+        browser.select(1);
+    }
+
+    let but_xpos = INNER_SELECT_ITEM_WIN_WIDTH/2 - BUT_WIDTH - BUT_SPACING/2;
+    let but_ypos = INNER_SELECT_ITEM_WIN_HEIGHT - BUT_HEIGHT - BUT_CHOICE_WIN_PADDING;
+
+    let mut but = _build_button_base(
+        BUT_WIDTH,
+        BUT_HEIGHT,
+        BUT_OK_LABEL,
+        _handle_button,
+        _draw_button
+    );
+    but.set_pos(but_xpos, but_ypos);
+    but.set_callback({
+        let mut win = select_win.clone();
+        let browser = browser.clone();
+        let choice = choice.clone();
+        move |_| {
+            // The browser lines start from 1, 0 means nothing is selected
+            let line = browser.value();
+            if line > 0 {
+                choice.set(Some(line as usize - 1));
+            }
+            win.hide();
+        }
+    });
+
+    let mut but = _build_button_base(
+        BUT_WIDTH,
+        BUT_HEIGHT,
+        BUT_CANCEL_LABEL,
+        _handle_button,
+        _draw_button
+    );
+    but.set_pos(but_xpos + BUT_WIDTH + BUT_SPACING, but_ypos);
+    but.set_callback({
+        let mut win = select_win.clone();
+        move |_| {
+            win.hide();
+        }
+    });
+
+
+    inner_win.end();
+
+    select_win.end();
+    select_win.hide();
+    select_win.make_modal(true);
+
+    return select_win;
+}
+
 
 /// Builds a pack for the end screens
 fn _build_end_but_pack(sender: Sender<Message>) -> Pack {
@@ -927,12 +1292,12 @@ fn _build_exit_button(sender: Sender<Message>) -> Button {
 }
 
 /// Builds the abort windows
-pub fn build_abort_win(sender: Sender<Message>) -> DoubleWindow {
-    let abort_win = build_inner_win();
+pub fn build_abort_win(sender: Sender<Message>, info_frame: &Frame) -> DoubleWindow {
+    let mut abort_win = build_inner_win();
     abort_win.begin();
 
     _build_top_frame(ABORT_TOP_FRAME_LABEL);
-    _build_mid_frame(ABORT_MID_FRAME_LABEL);
+    abort_win.add(info_frame);
 
     _build_exit_button(sender);
 
@@ -941,6 +1306,21 @@ pub fn build_abort_win(sender: Sender<Message>) -> DoubleWindow {
     return abort_win;
 }
 
+/// Builds the window shown after uninstallation
+pub fn build_uninstalled_win(sender: Sender<Message>) -> DoubleWindow {
+    let uninstalled_win = build_inner_win();
+    uninstalled_win.begin();
+
+    _build_top_frame(UNINSTALLED_TOP_FRAME_LABEL);
+    _build_mid_frame(UNINSTALLED_MID_FRAME_LABEL);
+
+    _build_exit_button(sender);
+
+    uninstalled_win.end();
+
+    return uninstalled_win;
+}
+
 /// Builds the done windows
 pub fn build_done_win(sender: Sender<Message>) -> DoubleWindow {
     let done_win = build_inner_win();
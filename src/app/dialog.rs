@@ -1,6 +1,10 @@
 /// The module that implements various dialogs
 
-use std::path::PathBuf;
+use std::{
+    cell::Cell,
+    path::PathBuf,
+    rc::Rc
+};
 
 use fltk::{
     app::wait,
@@ -15,7 +19,7 @@ use fltk::{
 };
 
 use crate::utils;
-use super::builder;
+use super::{builder, styles};
 
 
 /// Launches select directory dialog native to the target OS
@@ -36,6 +40,25 @@ pub fn run_select_dir_dlg(prompt: &str) -> PathBuf {
     return c.filename();
 }
 
+/// Launches select file dialog native to the target OS
+/// returns selected file, empty if the user cancelled
+pub fn run_select_file_dlg(prompt: &str, filter: &str) -> PathBuf {
+    let mut c = NativeFileChooser::new(NativeFileChooserType::BrowseFile);
+
+    c.set_title(prompt);
+    c.set_filter(filter);
+
+    let cwd = utils::get_cwd();
+    match c.set_directory(&cwd) {
+        Err(err) => eprintln!("Failed to automatically set default dir: {err}"),
+        Ok(_) => {}
+    };
+
+    c.show();
+
+    return c.filename();
+}
+
 /// Launches alert dialog
 /// NOTE: modal
 pub fn run_alert_dlg(msg: &str) {
@@ -61,3 +84,46 @@ pub fn run_msg_dlg(msg: &str) {
     }
     drop(win);
 }
+
+/// Launches choice dialog
+/// returns the index of the chosen option, None if the user closed the dialog
+/// NOTE: modal
+pub fn run_choice_dlg(msg: &str, options: &[&str]) -> Option<usize> {
+    let choice = Rc::new(Cell::new(None));
+    let mut win = builder::build_choice_win(
+        msg,
+        options,
+        choice.clone()
+    );
+    win.show();
+    while win.shown() {
+        wait();
+    }
+    drop(win);
+    return choice.get();
+}
+
+/// Launches select item dialog
+/// returns the index of the selected item, None if the user cancelled
+/// NOTE: modal
+pub fn run_select_item_dlg(msg: &str, items: &[String]) -> Option<usize> {
+    let choice = Rc::new(Cell::new(None));
+    let mut win = builder::build_select_item_win(
+        msg,
+        items,
+        choice.clone()
+    );
+    win.show();
+    while win.shown() {
+        wait();
+    }
+    drop(win);
+    return choice.get();
+}
+
+/// Launches confirmation dialog
+/// returns true if the user confirmed
+/// NOTE: modal
+pub fn run_confirm_dlg(msg: &str) -> bool {
+    return run_choice_dlg(msg, &[styles::BUT_YES_LABEL, styles::BUT_NO_LABEL]) == Some(0);
+}
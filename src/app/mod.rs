@@ -6,7 +6,11 @@ pub mod state;
 pub mod styles;
 
 
-use std::{thread, path::PathBuf};
+use std::{
+    cmp::Ordering,
+    thread,
+    path::{Path, PathBuf}
+};
 
 use fltk::{
     app::{
@@ -15,42 +19,91 @@ use fltk::{
         Sender,
         Receiver
     },
+    browser::CheckBrowser,
+    button::CheckButton,
+    enums::Cursor,
+    frame::Frame,
     text::TextBuffer,
+    menu::Choice,
     misc::Progress,
     prelude::{
         WidgetExt,
         GroupExt,
+        MenuExt,
+        WindowExt
     },
     window::DoubleWindow
 };
 use webbrowser;
 
 use state::{ThreadSafeState, build_thread_safe_state};
-use super::{audio, errors, installer, utils};
+use super::{audio, discovery, errors, installer, repair, saves, transaction, uninstaller, utils, version};
 use errors::InstallError;
 
 
+// Ids of the linked windows we need to switch to directly
+const SELECT_DIR_WINDOW_ID: usize = 2;
+const PROGRESS_WINDOW_ID: usize = 4;
+
+// The number of broken files to show in the verify report
+const VERIFY_REPORT_MAX_FILES: usize = 3;
+// The number of conflicting spritepack files to show
+const CONFLICTS_MAX_FILES: usize = 3;
+
+
 /// The message enum so different parts of the app can communicate
 #[derive(Clone, Copy)]
 pub enum Message {
     UpdateProgressBar(f64),
+    UpdateDownloadStats(installer::DownloadStats),
     Close,
     NextPage,
     PrevPage,
     SelectDir,
+    SelectFoundDir,
+    DdlcDirsFound,
     DlxVersionCheck,
     InstallSprCheck,
+    SprIntoGameCheck,
+    LocalMasArchiveCheck,
+    LocalSprArchiveCheck,
+    SelectRelease,
+    ReleasesFetched,
+    // The result is returned from the thread
+    InstalledTagFound,
     VolumeCheck,
     Install,
+    Uninstall,
+    Verify,
+    Restore,
     Preparing,
+    BackingUp,
     Downloading,
     Extracting,
+    // The entry is in the app state
+    ExtractingEntry,
+    // The number of written and skipped (unchanged) files
+    Extracted(usize, usize),
     DownloadingSpr,
+    ChecksumMissing,
+    TestingArchives,
+    SelectSpritepacks,
+    SpritepacksSelected,
+    SpritepackConflicts,
     ExtractingSpr,
     CleaningUp,
+    Uninstalling,
+    Verifying,
+    Repairing,
+    RollingBack,
     Error,
+    // Verification or repair failed, unlike Error we don't quit
+    VerifyFailed,
     Abort,
     Done,
+    Uninstalled,
+    Verified,
+    Repaired,
     OpenCredits,
     OpenChangelog
 }
@@ -77,16 +130,29 @@ pub struct InstallerApp {
     // These windows need to be available directly
     abort_window: DoubleWindow,
     done_window: DoubleWindow,
+    uninstalled_window: DoubleWindow,
+    spritepacks_window: DoubleWindow,
 
     // Audio manager, option because audio might not work
     audio_manager: Option<audio::AudioManager>,
 
     // Handle to the installer thread, option because we might not start it/close early
     installer_th_handle: Option<thread::JoinHandle<installer::InstallResult>>,
+    // Handle to the thread looking up the installed release before we can install
+    tag_finder_th_handle: Option<thread::JoinHandle<Result<Option<String>, InstallError>>>,
 
     // These need to be updated
     path_txt_buf: TextBuffer,
-    progress_bar: Progress
+    install_info_frame: Frame,
+    dir_report_frame: Frame,
+    release_choice: Choice,
+    found_dirs_choice: Choice,
+    local_mas_but: CheckButton,
+    local_spr_but: CheckButton,
+    spritepacks_browser: CheckBrowser,
+    progress_bar: Progress,
+    progress_info_frame: Frame,
+    abort_info_frame: Frame
 }
 
 impl InstallerApp {
@@ -97,7 +163,24 @@ impl InstallerApp {
         let state = build_thread_safe_state();
 
         let path_txt_buf = TextBuffer::default();
+        let install_info_frame = builder::build_install_info_frame();
+        let dir_report_frame = builder::build_dir_report_frame();
+        let release_choice = builder::build_release_choice(sender);
+        let found_dirs_choice = builder::build_found_dirs_choice(sender);
+        let local_mas_but = builder::build_local_archive_check_button(
+            styles::BUT_LOCAL_MAS_CHECK_LABEL,
+            sender,
+            Message::LocalMasArchiveCheck
+        );
+        let local_spr_but = builder::build_local_archive_check_button(
+            styles::BUT_LOCAL_SPR_CHECK_LABEL,
+            sender,
+            Message::LocalSprArchiveCheck
+        );
+        let spritepacks_browser = builder::build_spritepacks_browser();
         let progress_bar = builder::build_progress_bar();
+        let progress_info_frame = builder::build_progress_info_frame();
+        let abort_info_frame = builder::build_abort_info_frame();
 
         let main_window = builder::build_outer_win(sender, &state);
         main_window.begin();
@@ -106,20 +189,43 @@ impl InstallerApp {
             let s = state.lock().unwrap();
             let is_dlx_version = s.get_deluxe_ver_flag();
             let install_spr = s.get_install_spr_flag();
+            let spr_into_game = s.get_spr_into_game_flag();
             [
                 builder::build_welcome_win(sender, &state),
                 builder::build_license_win(sender, &state),
-                builder::build_select_dir_win(sender, &state, path_txt_buf.clone()),
-                builder::build_options_win(sender, &state, is_dlx_version, install_spr),
-                builder::build_propgress_win(sender, &state, &progress_bar)
+                builder::build_select_dir_win(
+                    sender,
+                    &state,
+                    path_txt_buf.clone(),
+                    &install_info_frame,
+                    &dir_report_frame,
+                    &found_dirs_choice
+                ),
+                builder::build_options_win(
+                    sender,
+                    &state,
+                    is_dlx_version,
+                    install_spr,
+                    spr_into_game,
+                    &release_choice,
+                    [&local_mas_but, &local_spr_but]
+                ),
+                builder::build_propgress_win(sender, &state, &progress_bar, &progress_info_frame)
             ]
         };
 
-        let abort_window = builder::build_abort_win(sender);
+        let abort_window = builder::build_abort_win(sender, &abort_info_frame);
         let done_window = builder::build_done_win(sender);
+        let uninstalled_window = builder::build_uninstalled_win(sender);
+        let spritepacks_window = builder::build_spritepacks_win(sender, &state, &spritepacks_browser);
 
         main_window.end();
 
+        // The list of releases isn't required, so we don't wait for it
+        installer::fetch_release_tags_in_thread(sender, &state);
+        // Same for the DDLC installations, the user can always browse
+        discovery::find_ddlc_dirs_in_thread(sender, &state);
+
         let audio_manager = match audio::play_theme() {
             Ok(s) => Some(s),
             Err(e) => {
@@ -138,10 +244,22 @@ impl InstallerApp {
             current_window_id: 0,
             abort_window,
             done_window,
+            uninstalled_window,
+            spritepacks_window,
             audio_manager,
             installer_th_handle: None,
+            tag_finder_th_handle: None,
             path_txt_buf,
-            progress_bar
+            install_info_frame,
+            dir_report_frame,
+            release_choice,
+            found_dirs_choice,
+            local_mas_but,
+            local_spr_but,
+            spritepacks_browser,
+            progress_bar,
+            progress_info_frame,
+            abort_info_frame
         };
         // Imprortant to set the dir again to update the text disp buffer
         installer.set_extraction_dir(utils::get_cwd());
@@ -162,6 +280,9 @@ impl InstallerApp {
                     Message::UpdateProgressBar(val) => {
                         self.progress_bar.set_value(val);
                     },
+                    Message::UpdateDownloadStats(stats) => {
+                        self.progress_info_frame.set_label(&utils::format_download_stats(&stats));
+                    },
                     Message::Close => {
                         break;
                     },
@@ -173,11 +294,65 @@ impl InstallerApp {
                     },
                     Message::SelectDir => {
                         let selected_dir = dialog::run_select_dir_dlg(styles::SEL_DIR_DLG_PROMPT);
-                        if !utils::is_valid_ddlc_dir(&selected_dir) {
-                            dialog::run_msg_dlg("Attention!\nSelected directory doesn't appear to be\na valid DDLC directory");
+                        let incompatibility = match selected_dir.is_dir() {
+                            true => utils::check_ddlc_dir(&selected_dir).incompatibility,
+                            false => None
+                        };
+                        if let Some(incompatibility) = incompatibility {
+                            dialog::run_alert_dlg(&incompatibility.get_explanation());
+                        }
+                        else if !utils::is_valid_ddlc_dir(&selected_dir) {
+                            let mut msg = String::from("Attention!\nSelected directory doesn't appear to be\na valid DDLC directory");
+                            if selected_dir.is_dir() {
+                                let missing: Vec<&str> = utils::check_ddlc_dir_layout(&selected_dir).get_missing_items().collect();
+                                if !missing.is_empty() {
+                                    msg.push_str(&format!("\nMissing: {}", missing.join(", ")));
+                                }
+                            }
+                            dialog::run_msg_dlg(&msg);
                         }
                         self.set_extraction_dir(selected_dir);
                     },
+                    Message::SelectFoundDir => {
+                        let found_dir = match self.found_dirs_choice.value() {
+                            i if i < 0 => None,
+                            i => self.state.lock().unwrap().get_found_ddlc_dirs().get(i as usize).cloned()
+                        };
+                        if let Some(found_dir) = found_dir {
+                            println!("Using {}...", found_dir.display());
+                            self.set_extraction_dir(found_dir);
+                        }
+                    },
+                    Message::DdlcDirsFound => {
+                        let found_dirs = self.state.lock().unwrap().get_found_ddlc_dirs().clone();
+                        self.found_dirs_choice.clear();
+                        if found_dirs.is_empty() {
+                            self.found_dirs_choice.add_choice(styles::FOUND_DIRS_CHOICE_NONE_LABEL);
+                            self.found_dirs_choice.set_value(0);
+                        }
+                        else {
+                            println!("Found {} DDLC installation(s)", found_dirs.len());
+                            for dir in found_dirs.iter() {
+                                self.found_dirs_choice.add_choice(&Self::escape_menu_label(&dir.to_string_lossy()));
+                            }
+                            self.found_dirs_choice.activate();
+                            // Nothing useful was selected yet, so we can pick for the user
+                            let extraction_dir = self.state.lock().unwrap().get_extraction_dir().clone();
+                            match found_dirs.iter().position(|dir| *dir == extraction_dir) {
+                                Some(i) => {
+                                    self.found_dirs_choice.set_value(i as i32);
+                                },
+                                None if !utils::is_valid_ddlc_dir(&extraction_dir) => {
+                                    self.found_dirs_choice.set_value(0);
+                                    self.set_extraction_dir(found_dirs[0].clone());
+                                },
+                                None => {
+                                    self.found_dirs_choice.set_value(-1);
+                                }
+                            };
+                        }
+                        self.redraw_current_window();
+                    },
                     Message::DlxVersionCheck => {
                         let mut app_state = self.state.lock().unwrap();
                         app_state.invert_deluxe_ver_flag();
@@ -194,6 +369,71 @@ impl InstallerApp {
                             false => println!("Excluding spritepacks...")
                         };
                     }
+                    Message::SprIntoGameCheck => {
+                        let mut app_state = self.state.lock().unwrap();
+                        app_state.invert_spr_into_game_flag();
+                        match app_state.get_spr_into_game_flag() {
+                            true => println!("Placing spritepacks into the game folder..."),
+                            false => println!("Placing spritepacks into the spritepacks folder...")
+                        };
+                    },
+                    Message::LocalMasArchiveCheck => {
+                        let archive = match self.local_mas_but.is_checked() {
+                            true => Self::select_local_archive(
+                                styles::SEL_MAS_ARCHIVE_DLG_PROMPT,
+                                "MAS",
+                                installer::is_mas_archive
+                            ),
+                            false => None
+                        };
+                        self.local_mas_but.set_checked(archive.is_some());
+                        match archive {
+                            Some(ref archive) => println!("Using local MAS archive '{}'...", archive.display()),
+                            None => println!("Using online installation...")
+                        };
+                        self.state.lock().unwrap().set_local_mas_archive(archive);
+                        self.redraw_current_window();
+                    },
+                    Message::LocalSprArchiveCheck => {
+                        let archive = match self.local_spr_but.is_checked() {
+                            true => Self::select_local_archive(
+                                styles::SEL_SPR_ARCHIVE_DLG_PROMPT,
+                                "spritepacks",
+                                installer::is_spr_archive
+                            ),
+                            false => None
+                        };
+                        self.local_spr_but.set_checked(archive.is_some());
+                        match archive {
+                            Some(ref archive) => println!("Using local spritepacks archive '{}'...", archive.display()),
+                            None => println!("Not using local spritepacks archive...")
+                        };
+                        self.state.lock().unwrap().set_local_spr_archive(archive);
+                        self.redraw_current_window();
+                    },
+                    Message::SelectRelease => {
+                        let mut app_state = self.state.lock().unwrap();
+                        // The first item is the latest release
+                        let tag = match self.release_choice.value() {
+                            i if i <= 0 => None,
+                            _ => self.release_choice.choice()
+                        };
+                        match tag {
+                            Some(ref tag) => println!("Using release {tag}..."),
+                            None => println!("Using the latest release...")
+                        };
+                        app_state.set_release_tag(tag);
+                    },
+                    Message::ReleasesFetched => {
+                        let app_state = self.state.lock().unwrap();
+                        for tag in app_state.get_release_tags() {
+                            self.release_choice.add_choice(tag);
+                        }
+                        drop(app_state);
+                        // Now we know the latest version
+                        self.update_install_info();
+                        self.redraw_current_window();
+                    },
                     Message::VolumeCheck => {
                         if let Some(ref am) = self.audio_manager {
                             let mut app_state = self.state.lock().unwrap();
@@ -212,45 +452,187 @@ impl InstallerApp {
                         }
                     }
                     Message::Install => {
-                        let app_state = self.state.lock().unwrap();
+                        // We're still looking up the installed release
+                        if self.tag_finder_th_handle.is_some() {
+                            continue;
+                        }
+                        if !self.check_interrupted_install() {
+                            continue;
+                        }
+                        let extraction_dir = self.state.lock().unwrap().get_extraction_dir().clone();
+                        // Some copies can't have MAS at all, for others the user might know better
+                        if let Some(incompatibility) = utils::check_ddlc_dir(&extraction_dir).incompatibility {
+                            if incompatibility.is_fatal() {
+                                dialog::run_alert_dlg(&incompatibility.get_explanation());
+                                continue;
+                            }
+                            let options = [styles::BUT_INSTALL_LABEL, styles::BUT_CANCEL_LABEL];
+                            if dialog::run_choice_dlg(&incompatibility.get_explanation(), &options) != Some(0) {
+                                continue;
+                            }
+                        }
                         // We warn the user again if the extraction dir looks wrong
-                        if !utils::is_valid_ddlc_dir(app_state.get_extraction_dir()) {
+                        else if !utils::is_valid_ddlc_dir(&extraction_dir) {
                             dialog::run_msg_dlg("Attention!\nInstalling into a non-DDLC directory");
                         }
-                        // We also need to move to the next window
-                        self.sender.send(Message::NextPage);
-                        // Consume any existing thread first
-                        // Have to drop the old ref
-                        drop(app_state);
+                        // MAS might be installed already, we'll need its release to reinstall it,
+                        // that requires the list of releases, so we continue once we have it
+                        let is_local = self.state.lock().unwrap().get_local_mas_archive().is_some();
+                        let is_installed = version::get_installed_version(&installer::get_destination(&self.state)).is_some();
+                        if is_installed && !is_local {
+                            self.main_window.set_cursor(Cursor::Wait);
+                            self.tag_finder_th_handle = Some(
+                                installer::find_installed_tag_in_thread(self.sender, &self.state)
+                            );
+                            continue;
+                        }
+                        if !self.confirm_install(Ok(None)) {
+                            continue;
+                        }
+                        self.start_install();
+                    },
+                    Message::InstalledTagFound => {
+                        self.main_window.set_cursor(Cursor::Default);
+                        let installed_tag = match self.tag_finder_th_handle.take().map(|th_handle| th_handle.join()) {
+                            Some(Ok(rv)) => rv,
+                            _ => {
+                                eprintln!("Failed to join the installed release lookup thread");
+                                Ok(None)
+                            }
+                        };
+                        if !self.confirm_install(installed_tag) {
+                            continue;
+                        }
+                        self.start_install();
+                    },
+                    Message::Uninstall => {
+                        if !self.check_interrupted_install() {
+                            continue;
+                        }
+                        if !dialog::run_confirm_dlg(styles::CONFIRM_UNINSTALL_MSG) {
+                            continue;
+                        }
+                        // Skip straight to the progress page
+                        self.change_window(PROGRESS_WINDOW_ID);
                         self.cleanup_th_handle();
-                        // Start a new thread
                         self.installer_th_handle = Some(
-                            installer::install_game_in_thread(self.sender, &self.state)
+                            uninstaller::uninstall_game_in_thread(self.sender, &self.state)
                         );
                     },
+                    Message::Verify => {
+                        if !self.check_interrupted_install() {
+                            continue;
+                        }
+                        self.change_window(PROGRESS_WINDOW_ID);
+                        self.cleanup_th_handle();
+                        self.installer_th_handle = Some(
+                            repair::verify_game_in_thread(self.sender, &self.state)
+                        );
+                    },
+                    Message::Restore => {
+                        self.restore_backup();
+                    },
                     Message::Preparing => {
                         println!("Preparing...");
                         self.progress_bar.set_label("Preparing...");
                     },
+                    Message::BackingUp => {
+                        println!("Done!\nBacking up saves...");
+                        self.progress_bar.set_label("Backing up saves...");
+                        self.progress_info_frame.set_label("");
+                    },
                     Message::Downloading => {
                         println!("Done!\nDownloading...");
                         self.progress_bar.set_label("Downloading...");
+                        self.progress_info_frame.set_label("");
                     },
                     Message::Extracting => {
                         println!("Done!\nExtracting...");
                         self.progress_bar.set_label("Extracting...");
+                        self.progress_info_frame.set_label("");
+                    },
+                    Message::ExtractingEntry => {
+                        let entry = self.state.lock().unwrap().get_current_entry().cloned();
+                        if let Some(entry) = entry {
+                            self.progress_info_frame.set_label(&format!("Extracting {entry}"));
+                        }
+                    },
+                    Message::Extracted(written, skipped) => {
+                        println!("Written {written} file(s), skipped {skipped} unchanged file(s)");
+                        self.progress_info_frame.set_label("");
                     },
                     Message::DownloadingSpr => {
                         println!("Done!\nDownloading spritepacks...");
                         self.progress_bar.set_label("Downloading spritepacks...");
+                        self.progress_info_frame.set_label("");
+                    },
+                    Message::ChecksumMissing => {
+                        println!("No checksum published, the download can't be verified");
+                        let label = self.progress_bar.label();
+                        self.progress_bar.set_label(&format!("{label} (not verified)"));
+                    },
+                    Message::TestingArchives => {
+                        println!("Done!\nChecking archives...");
+                        self.progress_bar.set_label("Checking archives...");
+                        self.progress_info_frame.set_label("");
+                    },
+                    Message::SelectSpritepacks => {
+                        println!("Done!\nSelecting spritepacks...");
+                        let spritepacks = self.state.lock().unwrap().get_spritepacks().clone();
+                        self.spritepacks_browser.clear();
+                        for spritepack in spritepacks.iter() {
+                            // All spritepacks are selected by default, same as before
+                            self.spritepacks_browser.add(spritepack, true);
+                        }
+                        self.hide_current_window();
+                        self.spritepacks_window.show();
+                    },
+                    Message::SpritepacksSelected => {
+                        let spritepacks = self.state.lock().unwrap().get_spritepacks().clone();
+                        // The browser lines start from 1
+                        let selected: Vec<String> = spritepacks.into_iter()
+                            .enumerate()
+                            .filter(|(i, _)| self.spritepacks_browser.checked(*i as i32 + 1))
+                            .map(|(_, spritepack)| spritepack)
+                            .collect();
+                        println!("Installing {} spritepack(s)...", selected.len());
+                        self.state.lock().unwrap().set_selected_spritepacks(Some(selected));
+                        self.spritepacks_window.hide();
+                        self.linked_windows[self.current_window_id].show();
+                    },
+                    Message::SpritepackConflicts => {
+                        let conflicts = self.state.lock().unwrap().get_spritepack_conflicts().clone();
+                        let msg = Self::format_spritepack_conflicts(&conflicts);
+                        let options = [styles::BUT_OVERWRITE_LABEL, styles::BUT_SKIP_LABEL];
+                        let overwrite = dialog::run_choice_dlg(&msg, &options) == Some(0);
+                        self.state.lock().unwrap().set_overwrite_spr_flag(Some(overwrite));
                     },
                     Message::ExtractingSpr => {
                         println!("Done!\nExtracting spritepacks...");
                         self.progress_bar.set_label("Extracting spritepacks...");
+                        self.progress_info_frame.set_label("");
                     },
                     Message::CleaningUp => {
                         println!("Done!\nCleaning up...");
                         self.progress_bar.set_label("Cleaning up...");
+                        self.progress_info_frame.set_label("");
+                    },
+                    Message::Uninstalling => {
+                        println!("Done!\nUninstalling...");
+                        self.progress_bar.set_label("Uninstalling...");
+                    },
+                    Message::Verifying => {
+                        println!("Done!\nVerifying...");
+                        self.progress_bar.set_label("Verifying...");
+                    },
+                    Message::Repairing => {
+                        println!("Done!\nRepairing...");
+                        self.progress_bar.set_label("Repairing...");
+                    },
+                    Message::RollingBack => {
+                        println!("Restoring the original files...");
+                        self.progress_bar.set_label("Restoring files...");
+                        self.progress_info_frame.set_label("");
                     },
                     Message::Error => {
                         println!("An error has occurred...");
@@ -263,11 +645,23 @@ impl InstallerApp {
                         // Let's just quit
                         self.sender.send(Message::Close);
                     },
+                    Message::VerifyFailed => {
+                        println!("Verification has failed...");
+                        // Nothing was changed (or it was rolled back), the user can try again
+                        if let Some(e) = self.cleanup_th_handle() {
+                            dialog::run_alert_dlg(&format!("{e}"));
+                        }
+                        self.change_window(SELECT_DIR_WINDOW_ID);
+                    },
                     Message::Abort => {
                         println!("Installation has been aborted!");
                         self.abort_installation();
                         self.cleanup_th_handle();
+                        // The thread is done, so is the rollback
+                        self.update_abort_info();
                         self.hide_current_window();
+                        // The user might abort while selecting spritepacks
+                        self.spritepacks_window.hide();
                         self.abort_window.show();
                     },
                     Message::Done => {
@@ -276,13 +670,55 @@ impl InstallerApp {
                         self.hide_current_window();
                         self.done_window.show();
                     },
+                    Message::Uninstalled => {
+                        println!("Done!\nUninstallation is complete!");
+                        self.abort_installation();
+                        self.hide_current_window();
+                        self.uninstalled_window.show();
+                    },
+                    Message::Verified => {
+                        self.cleanup_th_handle();
+                        let report = self.state.lock().unwrap().take_verify_report().unwrap_or_default();
+                        for file in report.missing.iter() {
+                            println!("Missing: {file}");
+                        }
+                        for file in report.modified.iter() {
+                            println!("Modified: {file}");
+                        }
+                        if report.is_ok() {
+                            dialog::run_msg_dlg("All files are intact");
+                            self.change_window(SELECT_DIR_WINDOW_ID);
+                            continue;
+                        }
+                        let options = [styles::BUT_REPAIR_LABEL, styles::BUT_SKIP_LABEL];
+                        match dialog::run_choice_dlg(&Self::format_verify_report(&report), &options) {
+                            Some(0) => {
+                                self.installer_th_handle = Some(
+                                    repair::repair_game_in_thread(self.sender, &self.state, report)
+                                );
+                            },
+                            _ => self.change_window(SELECT_DIR_WINDOW_ID)
+                        };
+                    },
+                    Message::Repaired => {
+                        println!("Done!\nRepair is complete!");
+                        self.cleanup_th_handle();
+                        dialog::run_msg_dlg("The broken files have been repaired");
+                        self.change_window(SELECT_DIR_WINDOW_ID);
+                    },
                     Message::OpenCredits => {
                         if let Err(e) = webbrowser::open(crate::CREDITS_URL) {
                             eprintln!("Failed to open browser {e}");
                         };
                     },
                     Message::OpenChangelog => {
-                        if let Err(e) = webbrowser::open(crate::CHANGELOG_URL) {
+                        let url = {
+                            let app_state = self.state.lock().unwrap();
+                            app_state.get_release_source().get_changelog_url(
+                                app_state.get_release_tag().map(String::as_str)
+                            )
+                        };
+                        if let Err(e) = webbrowser::open(&url) {
                             eprintln!("Failed to open browser {e}");
                         };
                     }
@@ -361,13 +797,304 @@ impl InstallerApp {
         app_state.set_abort_flag(true);
     }
 
+    /// Asks the user to select an archive for offline installation
+    /// Returns None if the user cancelled or the archive is invalid
+    fn select_local_archive(prompt: &str, kind: &str, is_valid: fn(&Path) -> bool) -> Option<PathBuf> {
+        let archive = dialog::run_select_file_dlg(prompt, styles::SEL_ARCHIVE_DLG_FILTER);
+        if archive.as_os_str().is_empty() {
+            return None;
+        }
+        if !is_valid(&archive) {
+            dialog::run_msg_dlg(&format!("Attention!\nSelected file doesn't appear to be\na valid {kind} archive"));
+            return None;
+        }
+        return Some(archive);
+    }
+
     /// Updates the extraction dir and text display with the path
     fn set_extraction_dir(&mut self, new_dir: PathBuf) {
         if new_dir.is_dir() && new_dir.parent().is_some() {
             let mut app_state = self.state.lock().unwrap();
             app_state.set_extraction_dir(new_dir);
             self.path_txt_buf.set_text(app_state.get_extraction_dir_str());
+            drop(app_state);
+            self.check_interrupted_install();
+            self.update_install_info();
+            self.update_dir_report();
+        }
+    }
+
+    /// Offers to restore the files left by an installation that didn't finish (e.g. crashed)
+    /// Returns false if there're such files and we failed to restore them
+    fn check_interrupted_install(&mut self) -> bool {
+        let extraction_dir = self.state.lock().unwrap().get_extraction_dir().clone();
+        let staging_dirs = installer::find_interrupted_installs(&extraction_dir);
+        if staging_dirs.is_empty() {
+            return true;
+        }
+
+        let msg = format!(
+            "A previous installation was interrupted,\nthe original files are kept in '{}'\nRestore them?",
+            staging_dirs[0].display()
+        );
+        let options = [styles::BUT_RESTORE_LABEL, styles::BUT_SKIP_LABEL];
+        if dialog::run_choice_dlg(&msg, &options) != Some(0) {
+            return true;
+        }
+
+        let mut failed: usize = 0;
+        for staging_dir in staging_dirs.iter() {
+            println!("Restoring files from {}...", staging_dir.display());
+            match transaction::recover_staging_dir(staging_dir) {
+                Ok(count) => failed += count,
+                Err(e) => {
+                    eprintln!("Failed to restore files from {}: {e}", staging_dir.display());
+                    failed += 1;
+                }
+            };
+        }
+        if failed > 0 {
+            dialog::run_alert_dlg(&format!("Failed to restore {failed} file(s),\nsee the console for details"));
+            return false;
+        }
+        dialog::run_msg_dlg("The original files have been restored");
+        self.update_install_info();
+        self.update_dir_report();
+        return true;
+    }
+
+    /// Escapes the characters fltk menus treat specially, e.g. '/' makes a submenu
+    fn escape_menu_label(label: &str) -> String {
+        let mut rv = String::with_capacity(label.len());
+        for c in label.chars() {
+            match c {
+                '/' | '\\' | '_' => rv.push('\\'),
+                '&' => rv.push('&'),
+                _ => {}
+            };
+            rv.push(c);
+        }
+        return rv;
+    }
+
+    /// Formats the verify report to show to the user
+    fn format_verify_report(report: &repair::VerifyReport) -> String {
+        let mut rv = format!(
+            "{} missing and {} modified file(s)\n",
+            report.missing.len(),
+            report.modified.len()
+        );
+        let total = report.missing.len() + report.modified.len();
+        for file in report.get_broken_files().take(VERIFY_REPORT_MAX_FILES) {
+            rv.push_str(file);
+            rv.push('\n');
+        }
+        if total > VERIFY_REPORT_MAX_FILES {
+            rv.push_str(&format!("and {} more", total - VERIFY_REPORT_MAX_FILES));
         }
+        return rv.trim_end().to_string();
+    }
+
+    /// Lets the user pick a backup of the persistent and puts it back
+    fn restore_backup(&mut self) {
+        let backups = match saves::list_backups() {
+            Ok(backups) => backups,
+            Err(e) => {
+                dialog::run_alert_dlg(&format!("Failed to list backups: {e}"));
+                return;
+            }
+        };
+        if backups.is_empty() {
+            dialog::run_msg_dlg("There are no backups yet");
+            return;
+        }
+
+        let items: Vec<String> = backups.iter()
+            .map(|backup| format!("{}  ({})", backup.name, utils::format_size(backup.size)))
+            .collect();
+        let backup = match dialog::run_select_item_dlg(styles::SELECT_BACKUP_MSG, &items) {
+            Some(i) => &backups[i],
+            None => return
+        };
+        if !dialog::run_confirm_dlg(styles::CONFIRM_RESTORE_MSG) {
+            return;
+        }
+
+        let mas_dir = installer::get_destination(&self.state);
+        match saves::restore_backup(&backup.path, &mas_dir) {
+            Ok(_) => {
+                println!("Restored backup {}", backup.name);
+                dialog::run_msg_dlg("The backup has been restored");
+            },
+            Err(e) => {
+                eprintln!("Failed to restore backup {}: {e}", backup.name);
+                dialog::run_alert_dlg(&format!("Failed to restore the backup: {e}"));
+            }
+        };
+    }
+
+    /// Formats the spritepack conflicts to show to the user
+    fn format_spritepack_conflicts(conflicts: &[String]) -> String {
+        let mut rv = format!("{} spritepack file(s) would replace existing ones\n", conflicts.len());
+        for file in conflicts.iter().take(CONFLICTS_MAX_FILES) {
+            rv.push_str(file);
+            rv.push('\n');
+        }
+        if conflicts.len() > CONFLICTS_MAX_FILES {
+            rv.push_str(&format!("and {} more", conflicts.len() - CONFLICTS_MAX_FILES));
+        }
+        return rv.trim_end().to_string();
+    }
+
+    /// Updates the frame with the installed and the latest versions of MAS
+    fn update_install_info(&mut self) {
+        let installed = version::get_installed_version(&installer::get_destination(&self.state));
+        let latest = self.state.lock().unwrap().get_release_tags().first().cloned();
+        self.install_info_frame.set_label(
+            &format!(
+                "Installed: {}    Latest: {}",
+                installed.as_deref().unwrap_or("none"),
+                latest.as_deref().unwrap_or("unknown")
+            )
+        );
+    }
+
+    /// Updates the frame with what happened to the files after an abort,
+    /// they are restored only if a rollback succeeded, verification doesn't change anything
+    fn update_abort_info(&mut self) {
+        let label = match self.state.lock().unwrap().get_rollback_failed() {
+            None => styles::ABORT_MID_FRAME_LABEL.to_string(),
+            Some(0) => styles::ABORT_RESTORED_MID_FRAME_LABEL.to_string(),
+            Some(failed) => format!("{}.\nFailed to restore {failed} file(s)", styles::ABORT_MID_FRAME_LABEL)
+        };
+        self.abort_info_frame.set_label(&label);
+    }
+
+    /// Updates the frame with the checklist of the selected dir
+    fn update_dir_report(&mut self) {
+        let extraction_dir = self.state.lock().unwrap().get_extraction_dir().clone();
+        let report = utils::check_ddlc_dir(&extraction_dir);
+        self.dir_report_frame.set_label(&Self::format_dir_report(&report));
+    }
+
+    /// Formats the DDLC dir report as a checklist
+    fn format_dir_report(report: &utils::DdlcDirReport) -> String {
+        let mark = |found: bool| {
+            return match found {
+                true => "[x]",
+                false => "[  ]"
+            };
+        };
+
+        let items = match report.layout.is_readable {
+            true => report.layout.items.iter()
+                .map(|(name, found)| format!("{} {name}", mark(*found)))
+                .collect::<Vec<String>>()
+                .join("   "),
+            false => String::from("Failed to read the content of the folder")
+        };
+        let mas = match report.mas_version {
+            Some(ref version) => format!("{} MAS installed ({version})", mark(true)),
+            None => format!("{} MAS installed", mark(false))
+        };
+        let mut rv = format!("{items}\n{} Writable   {mas}", mark(report.is_writable));
+        if let Some(ref incompatibility) = report.incompatibility {
+            rv.push_str(&format!("\n{}", incompatibility.get_summary()));
+        }
+        return rv;
+    }
+
+    /// Moves to the progress window and starts the installer thread
+    fn start_install(&mut self) {
+        // We also need to move to the next window
+        self.sender.send(Message::NextPage);
+        // Consume any existing thread first
+        self.cleanup_th_handle();
+        // Start a new thread
+        self.installer_th_handle = Some(
+            installer::install_game_in_thread(self.sender, &self.state)
+        );
+    }
+
+    /// Asks the user what to do if MAS is already installed: upgrade, reinstall or skip
+    /// Downgrades are only allowed if the user explicitly asked for them
+    /// Takes the result of the installed release lookup, it's done in a thread beforehand
+    /// Returns false if the installation should be skipped
+    fn confirm_install(&mut self, installed_tag: Result<Option<String>, InstallError>) -> bool {
+        let installed = match version::get_installed_version(&installer::get_destination(&self.state)) {
+            Some(installed) => installed,
+            None => return true
+        };
+
+        let is_local = self.state.lock().unwrap().get_local_mas_archive().is_some();
+
+        let target = {
+            let app_state = self.state.lock().unwrap();
+            match app_state.get_local_mas_archive() {
+                Some(mas_archive) => installer::get_archive_version(mas_archive),
+                None => app_state.get_release_tag().or(app_state.get_release_tags().first()).cloned()
+            }
+        };
+        let ordering = target.as_deref().and_then(|target| version::compare_versions(target, &installed));
+
+        match ordering {
+            // The local archive is the only release we have, so it's upgrade or nothing
+            Some(Ordering::Greater) if is_local => {
+                let msg = format!("Installed {installed}, the archive has {}\nUpgrade?", target.unwrap());
+                let options = [styles::BUT_UPGRADE_LABEL, styles::BUT_SKIP_LABEL];
+                return dialog::run_choice_dlg(&msg, &options) == Some(0);
+            },
+            Some(Ordering::Greater) => {
+                let msg = format!("Installed {installed}, available {}\nUpgrade or reinstall the current version?", target.unwrap());
+                let options = [styles::BUT_UPGRADE_LABEL, styles::BUT_REINSTALL_LABEL, styles::BUT_SKIP_LABEL];
+                return match dialog::run_choice_dlg(&msg, &options) {
+                    Some(0) => true,
+                    Some(1) => self.select_installed_release(installed_tag),
+                    _ => false
+                };
+            },
+            Some(Ordering::Less) => {
+                let msg = format!("Installed {installed} is newer than {}\nDowngrading may break your game", target.unwrap());
+                let options = [styles::BUT_DOWNGRADE_LABEL, styles::BUT_SKIP_LABEL];
+                if dialog::run_choice_dlg(&msg, &options) != Some(0) {
+                    return false;
+                }
+                println!("Downgrading...");
+                self.state.lock().unwrap().set_allow_downgrade_flag(true);
+                return true;
+            },
+            // Same version or we can't tell
+            _ => {
+                let msg = format!("Monika After Story {installed}\nis already installed");
+                let options = [styles::BUT_REINSTALL_LABEL, styles::BUT_SKIP_LABEL];
+                if dialog::run_choice_dlg(&msg, &options) != Some(0) {
+                    return false;
+                }
+                // Otherwise we'd install the latest release, whatever it is
+                if target.is_none() && !is_local {
+                    return self.select_installed_release(installed_tag);
+                }
+                return true;
+            }
+        };
+    }
+
+    /// Makes the installer use the installed release found by the lookup thread
+    /// Returns false if we couldn't find the release
+    fn select_installed_release(&mut self, installed_tag: Result<Option<String>, InstallError>) -> bool {
+        return match installed_tag {
+            Ok(tag) => {
+                if let Some(ref tag) = tag {
+                    println!("Reinstalling release {tag}...");
+                }
+                self.state.lock().unwrap().set_release_tag(tag);
+                true
+            },
+            Err(e) => {
+                dialog::run_alert_dlg(&format!("Failed to find the installed release:\n{e}"));
+                false
+            }
+        };
     }
 }
 
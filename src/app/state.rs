@@ -7,7 +7,11 @@ use std::{
         Mutex
     },
 };
-use crate::audio::Volume;
+use crate::{
+    audio::Volume,
+    installer::ReleaseSource,
+    repair::VerifyReport
+};
 
 
 pub type ThreadSafeState = Arc<Mutex<AppState>>;
@@ -20,7 +24,35 @@ pub struct AppState {
     abort_flag: bool,
     deluxe_ver_flag: bool,
     install_spr_flag: bool,
-    music_volume: Volume
+    music_volume: Volume,
+    // None means the latest release
+    release_tag: Option<String>,
+    // Tags of the releases the user can choose from
+    release_tags: Vec<String>,
+    release_source: ReleaseSource,
+    // Local archives for offline installation
+    local_mas_archive: Option<PathBuf>,
+    local_spr_archive: Option<PathBuf>,
+    // Whether we can install a release older than the installed one
+    allow_downgrade: bool,
+    // The result of the last verification
+    verify_report: Option<VerifyReport>,
+    // The spritepacks in the archive the user can choose from
+    spritepacks: Vec<String>,
+    // The spritepacks to install, None means the user hasn't chosen yet
+    selected_spritepacks: Option<Vec<String>>,
+    // Whether we place the spritepacks into the game dir
+    spr_into_game_flag: bool,
+    // The spritepack files that would replace existing files
+    spritepack_conflicts: Vec<String>,
+    // Whether we replace the conflicting files, None means the user hasn't chosen yet
+    overwrite_spr_flag: Option<bool>,
+    // The archive entry being extracted
+    current_entry: Option<String>,
+    // The number of paths the rollback failed to restore, None if nothing was rolled back
+    rollback_failed: Option<usize>,
+    // DDLC installations we found on the disk
+    found_ddlc_dirs: Vec<PathBuf>
 }
 
 impl AppState {
@@ -37,7 +69,22 @@ impl AppState {
             abort_flag,
             deluxe_ver_flag,
             install_spr_flag,
-            music_volume
+            music_volume,
+            release_tag: None,
+            release_tags: Vec::new(),
+            release_source: ReleaseSource::from_env(),
+            local_mas_archive: None,
+            local_spr_archive: None,
+            allow_downgrade: false,
+            verify_report: None,
+            spritepacks: Vec::new(),
+            selected_spritepacks: None,
+            spr_into_game_flag: false,
+            spritepack_conflicts: Vec::new(),
+            overwrite_spr_flag: None,
+            current_entry: None,
+            rollback_failed: None,
+            found_ddlc_dirs: Vec::new()
         };
     }
 
@@ -107,6 +154,171 @@ impl AppState {
     pub fn set_music_volume(&mut self, value: Volume) {
         self.music_volume = value;
     }
+
+    /// Returns the tag of the release to install, None means the latest one
+    pub fn get_release_tag(&self) -> Option<&String> {
+        return self.release_tag.as_ref();
+    }
+
+    /// Sets the tag of the release to install
+    pub fn set_release_tag(&mut self, value: Option<String>) {
+        self.release_tag = value;
+    }
+
+    /// Returns the tags of the available releases
+    pub fn get_release_tags(&self) -> &Vec<String> {
+        return &self.release_tags;
+    }
+
+    /// Sets the tags of the available releases
+    pub fn set_release_tags(&mut self, value: Vec<String>) {
+        self.release_tags = value;
+    }
+
+    /// Returns the source of the releases
+    pub fn get_release_source(&self) -> &ReleaseSource {
+        return &self.release_source;
+    }
+
+    /// Sets the source of the releases
+    pub fn set_release_source(&mut self, value: ReleaseSource) {
+        self.release_source = value;
+    }
+
+    /// Returns the local MAS archive, if it's set, the installation is offline
+    pub fn get_local_mas_archive(&self) -> Option<&PathBuf> {
+        return self.local_mas_archive.as_ref();
+    }
+
+    /// Sets the local MAS archive
+    pub fn set_local_mas_archive(&mut self, value: Option<PathBuf>) {
+        self.local_mas_archive = value;
+    }
+
+    /// Returns the local spritepacks archive
+    pub fn get_local_spr_archive(&self) -> Option<&PathBuf> {
+        return self.local_spr_archive.as_ref();
+    }
+
+    /// Sets the local spritepacks archive
+    pub fn set_local_spr_archive(&mut self, value: Option<PathBuf>) {
+        self.local_spr_archive = value;
+    }
+
+    /// Returns the allow downgrade flag
+    pub fn get_allow_downgrade_flag(&self) -> bool {
+        return self.allow_downgrade;
+    }
+
+    /// Sets the allow downgrade flag
+    pub fn set_allow_downgrade_flag(&mut self, value: bool) {
+        self.allow_downgrade = value;
+    }
+
+    /// Takes the result of the last verification
+    pub fn take_verify_report(&mut self) -> Option<VerifyReport> {
+        return self.verify_report.take();
+    }
+
+    /// Sets the result of the last verification
+    pub fn set_verify_report(&mut self, value: Option<VerifyReport>) {
+        self.verify_report = value;
+    }
+
+    /// Returns the spritepacks the user can choose from
+    pub fn get_spritepacks(&self) -> &Vec<String> {
+        return &self.spritepacks;
+    }
+
+    /// Sets the spritepacks the user can choose from
+    pub fn set_spritepacks(&mut self, value: Vec<String>) {
+        self.spritepacks = value;
+    }
+
+    /// Returns the spritepacks to install
+    pub fn get_selected_spritepacks(&self) -> Option<&Vec<String>> {
+        return self.selected_spritepacks.as_ref();
+    }
+
+    /// Takes the spritepacks to install
+    pub fn take_selected_spritepacks(&mut self) -> Option<Vec<String>> {
+        return self.selected_spritepacks.take();
+    }
+
+    /// Sets the spritepacks to install
+    pub fn set_selected_spritepacks(&mut self, value: Option<Vec<String>>) {
+        self.selected_spritepacks = value;
+    }
+
+    /// Returns the flag whether we place the spritepacks into the game dir
+    pub fn get_spr_into_game_flag(&self) -> bool {
+        return self.spr_into_game_flag;
+    }
+
+    /// Sets the flag whether we place the spritepacks into the game dir
+    pub fn set_spr_into_game_flag(&mut self, value: bool) {
+        self.spr_into_game_flag = value;
+    }
+
+    /// Inverts the flag whether we place the spritepacks into the game dir
+    pub fn invert_spr_into_game_flag(&mut self) {
+        self.spr_into_game_flag = !self.spr_into_game_flag;
+    }
+
+    /// Returns the spritepack files that would replace existing files
+    pub fn get_spritepack_conflicts(&self) -> &Vec<String> {
+        return &self.spritepack_conflicts;
+    }
+
+    /// Sets the spritepack files that would replace existing files
+    pub fn set_spritepack_conflicts(&mut self, value: Vec<String>) {
+        self.spritepack_conflicts = value;
+    }
+
+    /// Returns the flag whether we replace the conflicting files
+    pub fn get_overwrite_spr_flag(&self) -> Option<bool> {
+        return self.overwrite_spr_flag;
+    }
+
+    /// Takes the flag whether we replace the conflicting files
+    pub fn take_overwrite_spr_flag(&mut self) -> Option<bool> {
+        return self.overwrite_spr_flag.take();
+    }
+
+    /// Sets the flag whether we replace the conflicting files
+    pub fn set_overwrite_spr_flag(&mut self, value: Option<bool>) {
+        self.overwrite_spr_flag = value;
+    }
+
+    /// Returns the archive entry being extracted
+    pub fn get_current_entry(&self) -> Option<&String> {
+        return self.current_entry.as_ref();
+    }
+
+    /// Sets the archive entry being extracted
+    pub fn set_current_entry(&mut self, value: Option<String>) {
+        self.current_entry = value;
+    }
+
+    /// Returns the number of paths the rollback failed to restore
+    pub fn get_rollback_failed(&self) -> Option<usize> {
+        return self.rollback_failed;
+    }
+
+    /// Sets the number of paths the rollback failed to restore
+    pub fn set_rollback_failed(&mut self, value: Option<usize>) {
+        self.rollback_failed = value;
+    }
+
+    /// Returns the DDLC installations we found on the disk
+    pub fn get_found_ddlc_dirs(&self) -> &Vec<PathBuf> {
+        return &self.found_ddlc_dirs;
+    }
+
+    /// Sets the DDLC installations we found on the disk
+    pub fn set_found_ddlc_dirs(&mut self, value: Vec<PathBuf>) {
+        self.found_ddlc_dirs = value;
+    }
 }
 
 impl Default for AppState {
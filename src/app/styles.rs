@@ -19,6 +19,10 @@ pub const WIN_TITLE: &str = "Monika After Story Installer";
 pub const ALERT_WIN_TITLE: &str = "Error!";
 // Msg window title
 pub const MSG_WIN_TITLE: &str = "Attention!";
+// Choice window title
+pub const CHOICE_WIN_TITLE: &str = "Confirm";
+// Select item window title
+pub const SELECT_ITEM_WIN_TITLE: &str = "Select";
 
 
 // Window consts
@@ -42,6 +46,21 @@ pub const MSG_WIN_HEIGHT: i32 = ALERT_WIN_HEIGHT;
 pub const INNER_MSG_WIN_WIDTH: i32 = MSG_WIN_WIDTH - 2*WIN_PADDING;
 pub const INNER_MSG_WIN_HEIGHT: i32 = MSG_WIN_HEIGHT - 2*WIN_PADDING;
 
+pub const CHOICE_WIN_WIDTH: i32 = MSG_WIN_WIDTH;
+pub const CHOICE_WIN_HEIGHT: i32 = MSG_WIN_HEIGHT;
+
+pub const INNER_CHOICE_WIN_WIDTH: i32 = CHOICE_WIN_WIDTH - 2*WIN_PADDING;
+pub const INNER_CHOICE_WIN_HEIGHT: i32 = CHOICE_WIN_HEIGHT - 2*WIN_PADDING;
+
+pub const SELECT_ITEM_WIN_WIDTH: i32 = MSG_WIN_WIDTH;
+pub const SELECT_ITEM_WIN_HEIGHT: i32 = 320;
+
+pub const INNER_SELECT_ITEM_WIN_WIDTH: i32 = SELECT_ITEM_WIN_WIDTH - 2*WIN_PADDING;
+pub const INNER_SELECT_ITEM_WIN_HEIGHT: i32 = SELECT_ITEM_WIN_HEIGHT - 2*WIN_PADDING;
+
+pub const SELECT_ITEM_FRAME_HEIGHT: i32 = 50;
+pub const SELECT_ITEM_TXT_SIZE: i32 = 16;
+
 pub const INNER_WIN_CONTENT_XPADDING: i32 = 20;
 pub const INNER_WIN_CONTENT_YPADDING: i32 = INNER_WIN_CONTENT_XPADDING;
 
@@ -59,6 +78,21 @@ pub const BUT_DLX_VER_CHECK_HEIGHT: i32 = BUT_HEIGHT;
 pub const BUT_INSTALL_SPR_CHECK_WIDTH: i32 = BUT_WIDTH + 380;
 pub const BUT_INSTALL_SPR_CHECK_HEIGHT: i32 = BUT_HEIGHT;
 
+pub const BUT_SPR_INTO_GAME_CHECK_WIDTH: i32 = BUT_WIDTH + 380;
+pub const BUT_SPR_INTO_GAME_CHECK_HEIGHT: i32 = BUT_HEIGHT;
+
+pub const BUT_LOCAL_ARCHIVE_CHECK_WIDTH: i32 = BUT_WIDTH + 380;
+pub const BUT_LOCAL_ARCHIVE_CHECK_HEIGHT: i32 = BUT_HEIGHT;
+
+pub const RELEASE_CHOICE_WIDTH: i32 = BUT_WIDTH + 70;
+pub const RELEASE_CHOICE_HEIGHT: i32 = BUT_HEIGHT;
+// Space for the label on the left
+pub const RELEASE_CHOICE_XOFFSET: i32 = 80;
+
+pub const FOUND_DIRS_CHOICE_HEIGHT: i32 = BUT_HEIGHT;
+// Space for the label on the left
+pub const FOUND_DIRS_CHOICE_XOFFSET: i32 = 65;
+
 // pub const BUT_CREDITS_WIDTH: i32 = BUT_WIDTH;
 // pub const BUT_CREDITS_HEIGHT: i32 = BUT_HEIGHT;
 
@@ -76,14 +110,35 @@ pub const BUT_CONTINUE_LABEL: &str = " Continue@>";
 pub const BUT_SELECT_DIR_LABEL: &str = "Browse @fileopen";
 pub const BUT_DLX_VER_CHECK_LABEL: &str = "Deluxe version (pre-installed spritepacks)";
 pub const BUT_INSTALL_SPR_CHECK_LABEL: &str = "Download spritepacks (separate download into '/spritepacks')";
+pub const BUT_SPR_INTO_GAME_CHECK_LABEL: &str = "Place spritepacks directly into the game folder";
+pub const BUT_LOCAL_MAS_CHECK_LABEL: &str = "Install offline from a local MAS archive";
+pub const BUT_LOCAL_SPR_CHECK_LABEL: &str = "Use a local spritepacks archive";
+pub const RELEASE_CHOICE_LABEL: &str = "Version:";
+pub const RELEASE_CHOICE_LATEST_LABEL: &str = "Latest";
+pub const FOUND_DIRS_CHOICE_LABEL: &str = "Found:";
+pub const FOUND_DIRS_CHOICE_SEARCHING_LABEL: &str = "Searching...";
+pub const FOUND_DIRS_CHOICE_NONE_LABEL: &str = "No installations found, use Browse";
 pub const BUT_INSTALL_LABEL: &str = "Install";
 pub const BUT_OK_LABEL: &str = "Ok";
+pub const BUT_YES_LABEL: &str = "Yes";
+pub const BUT_NO_LABEL: &str = "No";
+pub const BUT_UNINSTALL_LABEL: &str = "Uninstall";
+pub const BUT_UPGRADE_LABEL: &str = "Upgrade";
+pub const BUT_REINSTALL_LABEL: &str = "Reinstall";
+pub const BUT_DOWNGRADE_LABEL: &str = "Downgrade";
+pub const BUT_SKIP_LABEL: &str = "Skip";
+pub const BUT_VERIFY_LABEL: &str = "Verify";
+pub const BUT_REPAIR_LABEL: &str = "Repair";
+pub const BUT_RESTORE_LABEL: &str = "Restore";
+pub const BUT_CANCEL_LABEL: &str = "Cancel";
+pub const BUT_OVERWRITE_LABEL: &str = "Overwrite";
 pub const BUT_EXIT_LABEL: &str = "Exit";
 pub const BUT_CREDITS_LABEL: &str = "MyNewSoundtrack\nYouTube channel";
 pub const BUT_CHANGELOG_LABEL: &str = "Changelog";
 
 pub const BUT_ALERT_WIN_PADDING: i32 = 10;
 pub const BUT_MSG_WIN_PADDING: i32 = BUT_ALERT_WIN_PADDING;
+pub const BUT_CHOICE_WIN_PADDING: i32 = BUT_ALERT_WIN_PADDING;
 pub const BUT_PACK_YPADDING: i32 = INNER_WIN_CONTENT_YPADDING;
 
 
@@ -123,7 +178,20 @@ pub const SEL_DIR_TXT_WIDTH: i32 = INNER_WIN_WIDTH - 2*INNER_WIN_CONTENT_XPADDIN
 pub const SEL_DIR_TXT_HEIGHT: i32 = 28;
 pub const SEL_DIR_TXT_SIZE: i32 = 18;
 
+pub const INSTALL_INFO_FRAME_YPOS: i32 = SEL_DIR_TXT_YPOS + SEL_DIR_TXT_HEIGHT + BUT_HEIGHT + 2*BUT_SPACING;
+pub const INSTALL_INFO_FRAME_HEIGHT: i32 = 30;
+pub const INSTALL_INFO_FRAME_LABEL_SIZE: i32 = SEL_DIR_TXT_SIZE;
+
+pub const DIR_REPORT_FRAME_YPOS: i32 = INSTALL_INFO_FRAME_YPOS + INSTALL_INFO_FRAME_HEIGHT + BUT_SPACING;
+pub const DIR_REPORT_FRAME_HEIGHT: i32 = 50;
+pub const DIR_REPORT_FRAME_LABEL_SIZE: i32 = 14;
+
+pub const SPR_BROWSER_TXT_SIZE: i32 = 16;
+
 pub const SEL_DIR_DLG_PROMPT: &str = "Select Doki Doki Literature Club directory";
+pub const SEL_MAS_ARCHIVE_DLG_PROMPT: &str = "Select Monika_After_Story-x.y.z-Mod(-Dlx).zip";
+pub const SEL_SPR_ARCHIVE_DLG_PROMPT: &str = "Select spritepacks.zip";
+pub const SEL_ARCHIVE_DLG_FILTER: &str = "*.zip";
 
 pub const LABEL_SIZE_LARGE: i32 = 28;
 pub const LABEL_SIZE_MED: i32 = 20;
@@ -133,6 +201,9 @@ pub const LABEL_SIZE_MED: i32 = 20;
 pub const PB_WIDTH: i32 = INNER_WIN_WIDTH - 2*INNER_WIN_CONTENT_XPADDING;
 pub const PB_HEIGHT: i32 = BUT_HEIGHT;
 
+pub const PROGRESS_INFO_FRAME_HEIGHT: i32 = 25;
+pub const PROGRESS_INFO_FRAME_LABEL_SIZE: i32 = 14;
+
 
 // Slider consts
 pub const SCROLL_AMOUNT: f64 = 3.0;
@@ -171,10 +242,27 @@ pub const LICENSE_FRAME_LABEL: &str = "By continuing you agree with our license"
 pub const SELECT_DIR_FRAME_LABEL: &str = "Select Doki Doki Literature Club directory";
 pub const OPTIONS_FRAME_LABEL: &str = "Select additional settings";
 pub const PROGRESS_FRAME_LABEL: &str = "Installing. Please wait";
+pub const SPRITEPACKS_FRAME_LABEL: &str = "Select spritepacks to install";
 pub const ABORT_TOP_FRAME_LABEL: &str = "Aborted";
-pub const ABORT_MID_FRAME_LABEL: &str = concat!(
+pub const ABORT_MID_FRAME_LABEL: &str = "Installation has been aborted";
+pub const ABORT_RESTORED_MID_FRAME_LABEL: &str = concat!(
     "Installation has been aborted.\n",
-    "Any already extracted files will remain"
+    "Your DDLC files have been restored"
+);
+pub const UNINSTALLED_TOP_FRAME_LABEL: &str = "Uninstalled";
+pub const UNINSTALLED_MID_FRAME_LABEL: &str = concat!(
+    "Monika After Story has been removed,\n",
+    "your DDLC files have been restored"
+);
+pub const CONFIRM_UNINSTALL_MSG: &str = concat!(
+    "Remove Monika After Story\n",
+    "from the selected directory?"
+);
+pub const SELECT_BACKUP_MSG: &str = "Select the backup to restore";
+pub const CONFIRM_RESTORE_MSG: &str = concat!(
+    "Replace the current persistent and saves\n",
+    "with the selected backup?\n",
+    "The current ones will be backed up first"
 );
 pub const DONE_TOP_FRAME_LABEL: &str = "Finished";
 pub const DONE_MID_FRAME_LABEL: &str = concat!(
@@ -0,0 +1,173 @@
+/// The module that implements the download cache of the installer
+/// Assets are kept there per release, so they can be reused by other installations,
+/// unfinished downloads are kept too, so they can be resumed after a restart
+
+use std::{
+    fs::{File, create_dir_all, read_dir, remove_dir_all, remove_file, rename},
+    io,
+    path::PathBuf,
+    time::SystemTime
+};
+
+use crate::{
+    installer::ContentSize,
+    utils
+};
+
+
+const RELEASES_DIR_NAME: &str = "releases";
+const PARTIAL_FILE_EXT: &str = "part";
+
+
+/// Represents a release in the cache
+#[derive(Debug)]
+pub struct CachedRelease {
+    pub tag: String,
+    pub size: ContentSize,
+    pub modified: SystemTime
+}
+
+
+/// Returns the dir with the cached releases
+pub fn get_releases_dir() -> PathBuf {
+    return utils::get_cache_dir().join(RELEASES_DIR_NAME);
+}
+
+/// Makes the tag safe to use as a dir name
+fn sanitize_tag(tag: &str) -> String {
+    return tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+}
+
+/// Returns the dir with the assets of the given release
+fn get_release_dir(tag: &str) -> PathBuf {
+    return get_releases_dir().join(sanitize_tag(tag));
+}
+
+/// Returns the path to the complete asset in the cache
+pub fn get_asset_path(tag: &str, name: &str) -> PathBuf {
+    return get_release_dir(tag).join(name);
+}
+
+/// Returns the path to the file the asset is being downloaded into
+fn get_partial_asset_path(tag: &str, name: &str) -> PathBuf {
+    return get_release_dir(tag).join(format!("{name}.{PARTIAL_FILE_EXT}"));
+}
+
+
+/// Opens the file to download the given asset into
+/// If the asset is already in the cache, opens it instead, the size is checked here,
+/// the hash is checked by the downloader, so the asset can be reused
+/// Otherwise opens the partial file, the existing content is kept so the download can continue
+pub fn open_asset_file(tag: &str, name: &str, size: ContentSize) -> Result<File, io::Error> {
+    let release_dir = get_release_dir(tag);
+    create_dir_all(&release_dir)?;
+
+    let complete_fp = get_asset_path(tag, name);
+    if complete_fp.is_file() {
+        if complete_fp.metadata()?.len() == size {
+            return File::options().write(true).read(true).open(&complete_fp);
+        }
+        // Different size, the release must've been updated
+        remove_file(&complete_fp)?;
+    }
+
+    let partial_fp = get_partial_asset_path(tag, name);
+    let file = File::options()
+        .write(true)
+        .read(true)
+        .create(true)
+        .open(&partial_fp)?;
+
+    // The file can't be bigger than the asset, something went wrong, start over
+    if file.metadata()?.len() > size {
+        file.set_len(0)?;
+    }
+
+    return Ok(file);
+}
+
+/// Returns how much of the given asset has been downloaded into the cache
+pub fn get_downloaded_size(tag: &str, name: &str, size: ContentSize) -> ContentSize {
+    if get_asset_path(tag, name).metadata().map_or(false, |m| m.len() == size) {
+        return size;
+    }
+    let partial_size = get_partial_asset_path(tag, name).metadata().map_or(0, |m| m.len());
+    // Bigger partial files are started over
+    return match partial_size > size {
+        true => 0,
+        false => partial_size
+    };
+}
+
+/// Marks the download of the given asset as complete, so other installations can use it
+/// Returns the complete file
+pub fn complete_asset_file(file: File, tag: &str, name: &str) -> Result<File, io::Error> {
+    // Windows doesn't allow to move open files
+    drop(file);
+
+    let partial_fp = get_partial_asset_path(tag, name);
+    let complete_fp = get_asset_path(tag, name);
+    if partial_fp.is_file() {
+        rename(&partial_fp, &complete_fp)?;
+    }
+
+    return File::open(&complete_fp);
+}
+
+
+/// Returns the size of all the files in the given dir
+fn get_dir_size(path: &PathBuf) -> Result<ContentSize, io::Error> {
+    let mut size = 0;
+    for item in read_dir(path)? {
+        let metadata = item?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    return Ok(size);
+}
+
+/// Returns the releases in the cache, the most recently updated first
+pub fn list_cached_releases() -> Result<Vec<CachedRelease>, io::Error> {
+    let releases_dir = get_releases_dir();
+    if !releases_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut releases = Vec::new();
+    for item in read_dir(&releases_dir)? {
+        let item = item?;
+        let path = item.path();
+        if !path.is_dir() {
+            continue;
+        }
+        releases.push(
+            CachedRelease {
+                tag: item.file_name().to_string_lossy().into_owned(),
+                size: get_dir_size(&path)?,
+                modified: item.metadata()?.modified()?
+            }
+        );
+    }
+    releases.sort_by(|a, b| b.modified.cmp(&a.modified));
+
+    return Ok(releases);
+}
+
+/// Removes the given release from the cache
+pub fn remove_cached_release(tag: &str) -> Result<(), io::Error> {
+    return remove_dir_all(get_release_dir(tag));
+}
+
+/// Removes all but the given number of the most recently updated releases from the cache
+/// Returns the removed releases
+pub fn prune_cached_releases(keep: usize) -> Result<Vec<CachedRelease>, io::Error> {
+    let mut releases = list_cached_releases()?;
+    let removed = releases.split_off(keep.min(releases.len()));
+    for release in removed.iter() {
+        remove_cached_release(&release.tag)?;
+    }
+    return Ok(removed);
+}
@@ -0,0 +1,942 @@
+/// The module that implements the command line interface of the installer
+/// It allows to install the game without a display (e.g. over SSH)
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    env,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex}
+};
+
+use crate::{
+    cache,
+    app::{
+        state::{AppState, ThreadSafeState},
+        Message
+    },
+    errors::ArgsError,
+    installer::{self, DownloadStats, MessageSender, ReleaseSource},
+    repair,
+    saves,
+    transaction,
+    uninstaller,
+    utils,
+    version
+};
+
+
+pub type ExitCode = i32;
+
+pub const EXIT_SUCCESS: ExitCode = 0;
+pub const EXIT_FAILURE: ExitCode = 1;
+pub const EXIT_USAGE: ExitCode = 2;
+
+// The width of the progress bar in characters
+const PB_WIDTH: usize = 40;
+// Enough to overwrite the longest stats line
+const STATS_WIDTH: usize = 48;
+
+const USAGE: &str = "\
+Usage: mas-installer [COMMAND] [OPTIONS]
+Running without a command launches the graphical installer
+
+Commands:
+    install                 Install Monika After Story without the GUI
+    uninstall               Remove Monika After Story and restore the vanilla DDLC files
+    verify                  Check the installed files against the release archive
+    releases                List the releases available for installation
+    cache list              List the releases in the download cache
+    cache prune             Remove releases from the download cache
+    backups list            List the backups of the persistent and saves
+    backups restore <NAME>  Restore the backup with the given name
+    recover                 Restore the files left by an installation that was interrupted
+    help                    Print this message
+    version                 Print the installer version
+
+Install options:
+    -d, --dir <PATH>        DDLC directory to install into (defaults to the current directory)
+        --deluxe            Install the deluxe version (pre-installed spritepacks)
+        --spritepacks       Download spritepacks (separate download into '<PATH>/spritepacks')
+        --spritepack <NAME> Install only the given spritepack, can be repeated (implies --spritepacks)
+        --spritepacks-into-game
+                            Place the spritepacks directly into '<PATH>/game' instead of '<PATH>/spritepacks'
+        --overwrite-conflicts
+                            Let the spritepacks replace existing files in '<PATH>/game' (skipped by default)
+        --tag <TAG>         Install the release with the given tag instead of the latest one
+        --mas-zip <FILE>    Install offline from a local Monika_After_Story-x.y.z-Mod(-Dlx).zip
+        --spr-zip <FILE>    Install spritepacks offline from a local spritepacks.zip
+        --allow-downgrade   Allow installing a release older than the installed one
+        --skip-if-installed Do nothing if the requested release (the latest by default) is already installed
+        --reinstall         Install the installed release again instead of the latest one
+
+Uninstall options:
+    -d, --dir <PATH>        DDLC directory to uninstall from (defaults to the current directory)
+        --tag <TAG>         Release to get the file list from if there's no install manifest
+        --mas-zip <FILE>    Local archive to get the file list from if there's no install manifest
+
+Verify options:
+    -d, --dir <PATH>        DDLC directory to verify (defaults to the current directory)
+        --repair            Re-extract the missing and modified files
+        --deluxe            Verify against the deluxe version if there's no install manifest
+        --tag <TAG>         Verify against the given release instead of the installed one
+        --mas-zip <FILE>    Verify against a local archive
+
+Cache prune options:
+        --keep <N>          Keep the given number of the most recently updated releases (default: 0)
+        --tag <TAG>         Remove only the release with the given tag
+
+Backups restore options:
+    -d, --dir <PATH>        DDLC directory to restore the game saves into (defaults to the current directory)
+
+Recover options:
+    -d, --dir <PATH>        DDLC directory the installation was interrupted in (defaults to the current directory)
+
+Release source options (install, uninstall, verify, releases):
+        --api-url <URL>     Base URL of the GitHub compatible API (env: MAS_INSTALLER_API_URL)
+        --repo <OWNER/NAME> Repository to get the releases from (env: MAS_INSTALLER_REPO)";
+
+
+/// Commands the CLI accepts
+enum Command {
+    Install(InstallArgs),
+    Uninstall(UninstallArgs),
+    Verify(VerifyArgs),
+    Releases(ReleaseSource),
+    Cache(CacheCommand),
+    Backups(BackupsCommand),
+    Recover(PathBuf),
+    Help,
+    Version
+}
+
+/// Subcommands of the cache command
+enum CacheCommand {
+    List,
+    Prune(usize),
+    Remove(String)
+}
+
+/// Subcommands of the backups command
+enum BackupsCommand {
+    List,
+    // The name of the backup and the DDLC dir
+    Restore(String, PathBuf)
+}
+
+/// Options of the install command
+struct InstallArgs {
+    dir: PathBuf,
+    deluxe: bool,
+    spritepacks: bool,
+    tag: Option<String>,
+    source: ReleaseSource,
+    mas_archive: Option<PathBuf>,
+    spr_archive: Option<PathBuf>,
+    // Empty means all the spritepacks
+    spritepack_names: Vec<String>,
+    spr_into_game: bool,
+    overwrite_conflicts: bool,
+    allow_downgrade: bool,
+    skip_if_installed: bool,
+    reinstall: bool
+}
+
+impl Default for InstallArgs {
+    fn default() -> Self {
+        return Self {
+            dir: utils::get_cwd(),
+            deluxe: false,
+            spritepacks: false,
+            tag: None,
+            source: ReleaseSource::from_env(),
+            mas_archive: None,
+            spr_archive: None,
+            spritepack_names: Vec::new(),
+            spr_into_game: false,
+            overwrite_conflicts: false,
+            allow_downgrade: false,
+            skip_if_installed: false,
+            reinstall: false
+        };
+    }
+}
+
+/// Options of the uninstall command
+struct UninstallArgs {
+    dir: PathBuf,
+    tag: Option<String>,
+    source: ReleaseSource,
+    mas_archive: Option<PathBuf>
+}
+
+impl Default for UninstallArgs {
+    fn default() -> Self {
+        return Self {
+            dir: utils::get_cwd(),
+            tag: None,
+            source: ReleaseSource::from_env(),
+            mas_archive: None
+        };
+    }
+}
+
+/// Options of the verify command
+struct VerifyArgs {
+    dir: PathBuf,
+    repair: bool,
+    deluxe: bool,
+    tag: Option<String>,
+    source: ReleaseSource,
+    mas_archive: Option<PathBuf>
+}
+
+impl Default for VerifyArgs {
+    fn default() -> Self {
+        return Self {
+            dir: utils::get_cwd(),
+            repair: false,
+            deluxe: false,
+            tag: None,
+            source: ReleaseSource::from_env(),
+            mas_archive: None
+        };
+    }
+}
+
+
+/// Prints the installer progress to the terminal
+struct ConsoleSender {
+    // The last printed percentage, None if there's no progress line
+    progress: Cell<Option<u32>>,
+    // The download stats printed after the percentage
+    stats: RefCell<String>
+}
+
+impl ConsoleSender {
+    /// Creates a new ConsoleSender
+    pub fn new() -> Self {
+        return Self {
+            progress: Cell::new(None),
+            stats: RefCell::new(String::new())
+        };
+    }
+
+    /// Ends the current progress line if we have one
+    pub fn finish_progress(&self) {
+        if self.progress.take().is_some() {
+            println!();
+        }
+        self.stats.borrow_mut().clear();
+    }
+
+    /// Prints the name of a new installation stage
+    fn print_stage(&self, stage: &str) {
+        self.finish_progress();
+        println!("{stage}");
+    }
+
+    /// Redraws the progress line, does nothing if the percentage hasn't changed
+    fn print_progress(&self, val: f64) {
+        let percent = (val.clamp(0.0, 1.0) * 100.0).round() as u32;
+        if self.progress.get() == Some(percent) {
+            return;
+        }
+        self.progress.set(Some(percent));
+        self.redraw_progress();
+    }
+
+    /// Updates the download stats on the progress line
+    fn print_download_stats(&self, stats: &DownloadStats) {
+        *self.stats.borrow_mut() = utils::format_download_stats(stats);
+        self.redraw_progress();
+    }
+
+    /// Prints the progress line over the previous one
+    fn redraw_progress(&self) {
+        let percent = self.progress.get().unwrap_or_default();
+        let filled = PB_WIDTH * percent as usize / 100;
+        print!(
+            "\r[{}{}] {:>3}%  {:<STATS_WIDTH$}",
+            "#".repeat(filled),
+            ".".repeat(PB_WIDTH-filled),
+            percent,
+            self.stats.borrow()
+        );
+        // Nothing we can do if stdout is gone
+        io::stdout().flush().ok();
+    }
+}
+
+impl MessageSender for ConsoleSender {
+    fn send(&self, msg: Message) {
+        match msg {
+            Message::UpdateProgressBar(val) => self.print_progress(val),
+            Message::UpdateDownloadStats(stats) => self.print_download_stats(&stats),
+            Message::Preparing => self.print_stage("Preparing..."),
+            Message::BackingUp => self.print_stage("Backing up saves..."),
+            Message::Downloading => self.print_stage("Downloading..."),
+            Message::Extracting => self.print_stage("Extracting..."),
+            Message::Extracted(written, skipped) => {
+                self.print_stage(&format!("Written {written} file(s), skipped {skipped} unchanged file(s)"));
+            },
+            Message::DownloadingSpr => self.print_stage("Downloading spritepacks..."),
+            Message::ChecksumMissing => self.print_stage("Warning: no checksum published, the download can't be verified"),
+            Message::TestingArchives => self.print_stage("Checking archives..."),
+            Message::ExtractingSpr => self.print_stage("Extracting spritepacks..."),
+            Message::CleaningUp => self.print_stage("Cleaning up..."),
+            Message::Uninstalling => self.print_stage("Uninstalling..."),
+            Message::Verifying => self.print_stage("Verifying..."),
+            Message::Repairing => self.print_stage("Repairing..."),
+            Message::RollingBack => self.print_stage("Restoring the original files..."),
+            Message::Done => self.print_stage("Installation is complete!"),
+            Message::Uninstalled => self.print_stage("Uninstallation is complete!"),
+            Message::Repaired => self.print_stage("Repair is complete!"),
+            // The rest are GUI events
+            _ => {}
+        };
+    }
+}
+
+
+/// Returns the arguments the installer was launched with
+fn get_args() -> Vec<String> {
+    return env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        // Old macOS passes the process serial number when launching an app bundle
+        .filter(|arg| !arg.starts_with("-psn_"))
+        .collect();
+}
+
+/// Returns true if the installer was launched with arguments and should run in CLI mode
+pub fn has_args() -> bool {
+    return !get_args().is_empty();
+}
+
+
+/// Returns the value of an option or an error if there's none
+fn get_option_value(args: &mut impl Iterator<Item = String>, option: &str) -> Result<String, ArgsError> {
+    return args.next().ok_or_else(|| ArgsError::MissingValue(option.to_string()));
+}
+
+/// Parses the release source options, returns false if the option is something else
+fn parse_source_option(
+    arg: &str,
+    args: &mut impl Iterator<Item = String>,
+    source: &mut ReleaseSource
+) -> Result<bool, ArgsError> {
+    match arg {
+        "--api-url" => {
+            let api_url = get_option_value(args, arg)?;
+            if !ReleaseSource::is_valid_api_url(&api_url) {
+                return Err(ArgsError::InvalidValue(arg.to_string(), api_url));
+            }
+            *source = ReleaseSource::new(&api_url, source.get_repo());
+        },
+        "--repo" => {
+            let repo = get_option_value(args, arg)?;
+            if !ReleaseSource::is_valid_repo(&repo) {
+                return Err(ArgsError::InvalidValue(arg.to_string(), repo));
+            }
+            *source = ReleaseSource::new(source.get_api_url(), &repo);
+        },
+        _ => return Ok(false)
+    };
+    return Ok(true);
+}
+
+/// Parses the options of the releases command
+fn parse_releases_args(mut args: impl Iterator<Item = String>) -> Result<ReleaseSource, ArgsError> {
+    let mut source = ReleaseSource::from_env();
+
+    while let Some(arg) = args.next() {
+        if !parse_source_option(&arg, &mut args, &mut source)? {
+            return Err(ArgsError::UnknownOption(arg));
+        }
+    }
+
+    return Ok(source);
+}
+
+/// Parses the subcommand and options of the cache command
+fn parse_cache_args(mut args: impl Iterator<Item = String>) -> Result<CacheCommand, ArgsError> {
+    let subcommand = args.next().ok_or_else(|| ArgsError::MissingCommand("cache".to_string()))?;
+
+    return match subcommand.as_str() {
+        "list" => {
+            if let Some(arg) = args.next() {
+                return Err(ArgsError::UnknownOption(arg));
+            }
+            Ok(CacheCommand::List)
+        },
+        "prune" => {
+            let mut command = CacheCommand::Prune(0);
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--keep" => {
+                        let keep = get_option_value(&mut args, &arg)?;
+                        let keep = keep.parse::<usize>().map_err(|_| ArgsError::InvalidValue(arg, keep))?;
+                        command = CacheCommand::Prune(keep);
+                    },
+                    "--tag" => command = CacheCommand::Remove(get_option_value(&mut args, &arg)?),
+                    _ => return Err(ArgsError::UnknownOption(arg))
+                };
+            }
+            Ok(command)
+        },
+        _ => Err(ArgsError::UnknownCommand(format!("cache {subcommand}")))
+    };
+}
+
+/// Parses the subcommand and options of the backups command
+fn parse_backups_args(mut args: impl Iterator<Item = String>) -> Result<BackupsCommand, ArgsError> {
+    let subcommand = args.next().ok_or_else(|| ArgsError::MissingCommand("backups".to_string()))?;
+
+    return match subcommand.as_str() {
+        "list" => {
+            if let Some(arg) = args.next() {
+                return Err(ArgsError::UnknownOption(arg));
+            }
+            Ok(BackupsCommand::List)
+        },
+        "restore" => {
+            let mut name = None;
+            let mut dir = utils::get_cwd();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "-d" | "--dir" => {
+                        let value = get_option_value(&mut args, &arg)?;
+                        dir = PathBuf::from(&value);
+                        if !dir.is_dir() {
+                            return Err(ArgsError::InvalidDir(value));
+                        }
+                    },
+                    _ if name.is_none() && !arg.starts_with('-') => name = Some(arg),
+                    _ => return Err(ArgsError::UnknownOption(arg))
+                };
+            }
+            let name = name.ok_or_else(|| ArgsError::MissingValue("backups restore".to_string()))?;
+            Ok(BackupsCommand::Restore(name, dir))
+        },
+        _ => Err(ArgsError::UnknownCommand(format!("backups {subcommand}")))
+    };
+}
+
+/// Parses the options of the recover command
+fn parse_recover_args(mut args: impl Iterator<Item = String>) -> Result<PathBuf, ArgsError> {
+    let mut dir = utils::get_cwd();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--dir" => {
+                let value = get_option_value(&mut args, &arg)?;
+                dir = PathBuf::from(&value);
+                if !dir.is_dir() {
+                    return Err(ArgsError::InvalidDir(value));
+                }
+            },
+            _ => return Err(ArgsError::UnknownOption(arg))
+        };
+    }
+    return Ok(dir);
+}
+
+/// Parses the options of the install command
+fn parse_install_args(mut args: impl Iterator<Item = String>) -> Result<InstallArgs, ArgsError> {
+    let mut install_args = InstallArgs::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--dir" => {
+                let dir = get_option_value(&mut args, &arg)?;
+                install_args.dir = PathBuf::from(&dir);
+                if !install_args.dir.is_dir() {
+                    return Err(ArgsError::InvalidDir(dir));
+                }
+            },
+            "--deluxe" => install_args.deluxe = true,
+            "--spritepacks" => install_args.spritepacks = true,
+            "--spritepack" => {
+                install_args.spritepacks = true;
+                install_args.spritepack_names.push(get_option_value(&mut args, &arg)?);
+            },
+            "--spritepacks-into-game" => install_args.spr_into_game = true,
+            "--overwrite-conflicts" => install_args.overwrite_conflicts = true,
+            "--tag" => install_args.tag = Some(get_option_value(&mut args, &arg)?),
+            "--mas-zip" => {
+                let archive = get_option_value(&mut args, &arg)?;
+                if !installer::is_mas_archive(Path::new(&archive)) {
+                    return Err(ArgsError::InvalidValue(arg, archive));
+                }
+                install_args.mas_archive = Some(PathBuf::from(archive));
+            },
+            "--spr-zip" => {
+                let archive = get_option_value(&mut args, &arg)?;
+                if !installer::is_spr_archive(Path::new(&archive)) {
+                    return Err(ArgsError::InvalidValue(arg, archive));
+                }
+                install_args.spr_archive = Some(PathBuf::from(archive));
+            },
+            "--allow-downgrade" => install_args.allow_downgrade = true,
+            "--skip-if-installed" => install_args.skip_if_installed = true,
+            "--reinstall" => install_args.reinstall = true,
+            _ => {
+                if !parse_source_option(&arg, &mut args, &mut install_args.source)? {
+                    return Err(ArgsError::UnknownOption(arg));
+                }
+            }
+        };
+    }
+
+    if install_args.reinstall {
+        if install_args.skip_if_installed {
+            return Err(ArgsError::ConflictingOptions("--reinstall".to_string(), "--skip-if-installed".to_string()));
+        }
+        if install_args.tag.is_some() {
+            return Err(ArgsError::ConflictingOptions("--reinstall".to_string(), "--tag".to_string()));
+        }
+    }
+
+    return Ok(install_args);
+}
+
+/// Parses the options of the uninstall command
+fn parse_uninstall_args(mut args: impl Iterator<Item = String>) -> Result<UninstallArgs, ArgsError> {
+    let mut uninstall_args = UninstallArgs::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--dir" => {
+                let dir = get_option_value(&mut args, &arg)?;
+                uninstall_args.dir = PathBuf::from(&dir);
+                if !uninstall_args.dir.is_dir() {
+                    return Err(ArgsError::InvalidDir(dir));
+                }
+            },
+            "--tag" => uninstall_args.tag = Some(get_option_value(&mut args, &arg)?),
+            "--mas-zip" => {
+                let archive = get_option_value(&mut args, &arg)?;
+                if !installer::is_mas_archive(Path::new(&archive)) {
+                    return Err(ArgsError::InvalidValue(arg, archive));
+                }
+                uninstall_args.mas_archive = Some(PathBuf::from(archive));
+            },
+            _ => {
+                if !parse_source_option(&arg, &mut args, &mut uninstall_args.source)? {
+                    return Err(ArgsError::UnknownOption(arg));
+                }
+            }
+        };
+    }
+
+    return Ok(uninstall_args);
+}
+
+/// Parses the options of the verify command
+fn parse_verify_args(mut args: impl Iterator<Item = String>) -> Result<VerifyArgs, ArgsError> {
+    let mut verify_args = VerifyArgs::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-d" | "--dir" => {
+                let dir = get_option_value(&mut args, &arg)?;
+                verify_args.dir = PathBuf::from(&dir);
+                if !verify_args.dir.is_dir() {
+                    return Err(ArgsError::InvalidDir(dir));
+                }
+            },
+            "--repair" => verify_args.repair = true,
+            "--deluxe" => verify_args.deluxe = true,
+            "--tag" => verify_args.tag = Some(get_option_value(&mut args, &arg)?),
+            "--mas-zip" => {
+                let archive = get_option_value(&mut args, &arg)?;
+                if !installer::is_mas_archive(Path::new(&archive)) {
+                    return Err(ArgsError::InvalidValue(arg, archive));
+                }
+                verify_args.mas_archive = Some(PathBuf::from(archive));
+            },
+            _ => {
+                if !parse_source_option(&arg, &mut args, &mut verify_args.source)? {
+                    return Err(ArgsError::UnknownOption(arg));
+                }
+            }
+        };
+    }
+
+    return Ok(verify_args);
+}
+
+/// Parses the given arguments into a command
+fn parse_args(args: Vec<String>) -> Result<Command, ArgsError> {
+    let mut args = args.into_iter();
+
+    let command = match args.next() {
+        None => return Ok(Command::Help),
+        Some(cmd) => cmd
+    };
+
+    return match command.as_str() {
+        "install" => Ok(Command::Install(parse_install_args(args)?)),
+        "uninstall" => Ok(Command::Uninstall(parse_uninstall_args(args)?)),
+        "verify" => Ok(Command::Verify(parse_verify_args(args)?)),
+        "releases" => Ok(Command::Releases(parse_releases_args(args)?)),
+        "cache" => Ok(Command::Cache(parse_cache_args(args)?)),
+        "backups" => Ok(Command::Backups(parse_backups_args(args)?)),
+        "recover" => Ok(Command::Recover(parse_recover_args(args)?)),
+        "help" | "-h" | "--help" => Ok(Command::Help),
+        "version" | "-V" | "--version" => Ok(Command::Version),
+        _ => Err(ArgsError::UnknownCommand(command))
+    };
+}
+
+
+/// Warns the user if an installation was interrupted in the selected dir
+fn warn_interrupted_install(app_state: &ThreadSafeState) {
+    let extraction_dir = app_state.lock().unwrap().get_extraction_dir().clone();
+    for staging_dir in installer::find_interrupted_installs(&extraction_dir) {
+        eprintln!(
+            "Attention! A previous installation was interrupted, the original files are kept in '{}'\n\
+            Run the recover command to restore them",
+            staging_dir.display()
+        );
+    }
+}
+
+/// Returns the version the installation would install, None if we can't tell
+fn get_target_version(app_state: &ThreadSafeState) -> Option<String> {
+    let (local_mas_archive, tag, source) = {
+        let app_state = app_state.lock().unwrap();
+        (
+            app_state.get_local_mas_archive().cloned(),
+            app_state.get_release_tag().cloned(),
+            app_state.get_release_source().clone()
+        )
+    };
+    if let Some(mas_archive) = local_mas_archive {
+        return installer::get_archive_version(&mas_archive);
+    }
+    if tag.is_some() {
+        return tag;
+    }
+    return match installer::build_client().and_then(|client| installer::get_release_tags(&client, &source)) {
+        Ok(tags) => tags.into_iter().next(),
+        Err(e) => {
+            eprintln!("Failed to fetch the list of releases: {e}");
+            None
+        }
+    };
+}
+
+/// Runs the installation without the GUI
+fn run_install(args: InstallArgs) -> ExitCode {
+    let report = utils::check_ddlc_dir(&args.dir);
+    if let Some(ref incompatibility) = report.incompatibility {
+        eprintln!("Attention! {}", incompatibility.get_explanation());
+        if incompatibility.is_fatal() {
+            return EXIT_FAILURE;
+        }
+    }
+    else if !utils::is_valid_ddlc_dir(&args.dir) {
+        eprintln!("Attention! '{}' doesn't appear to be a valid DDLC directory", args.dir.display());
+        let missing: Vec<&str> = report.layout.get_missing_items().collect();
+        if args.dir.is_dir() && !missing.is_empty() {
+            eprintln!("Missing: {}", missing.join(", "));
+        }
+    }
+    else if !report.is_writable {
+        eprintln!("Attention! Can't write into '{}'", args.dir.display());
+    }
+
+    let mut app_state = AppState::new(
+        args.dir,
+        false,
+        args.deluxe,
+        args.spritepacks,
+        0.0
+    );
+    app_state.set_release_tag(args.tag);
+    app_state.set_release_source(args.source);
+    app_state.set_local_mas_archive(args.mas_archive);
+    app_state.set_local_spr_archive(args.spr_archive);
+    app_state.set_allow_downgrade_flag(args.allow_downgrade);
+    if !args.spritepack_names.is_empty() {
+        app_state.set_selected_spritepacks(Some(args.spritepack_names));
+    }
+    app_state.set_spr_into_game_flag(args.spr_into_game);
+    app_state.set_overwrite_spr_flag(Some(args.overwrite_conflicts));
+    let app_state: ThreadSafeState = Arc::new(Mutex::new(app_state));
+    let sender = ConsoleSender::new();
+    warn_interrupted_install(&app_state);
+
+    let mas_dir = installer::get_destination(&app_state);
+    if let Some(installed) = version::get_installed_version(&mas_dir) {
+        println!("Found installed Monika After Story {installed}");
+        let is_local = app_state.lock().unwrap().get_local_mas_archive().is_some();
+        if args.reinstall && !is_local {
+            match installer::find_installed_tag(&app_state, &mas_dir) {
+                Ok(tag) => {
+                    if let Some(ref tag) = tag {
+                        println!("Reinstalling release {tag}...");
+                    }
+                    app_state.lock().unwrap().set_release_tag(tag);
+                },
+                Err(e) => {
+                    eprintln!("Failed to find the installed release: {e}");
+                    return EXIT_FAILURE;
+                }
+            };
+        }
+        else if args.skip_if_installed {
+            match get_target_version(&app_state) {
+                Some(target) if version::compare_versions(&target, &installed) == Some(Ordering::Equal) => {
+                    println!("Monika After Story {installed} is already installed, skipping");
+                    return EXIT_SUCCESS;
+                },
+                Some(_) => {},
+                None => println!("Can't tell the version of the requested release, installing anyway...")
+            };
+        }
+    }
+
+    return match installer::install_game(&sender, &app_state) {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            sender.finish_progress();
+            eprintln!("Installation failed: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Runs the uninstallation without the GUI
+fn run_uninstall(args: UninstallArgs) -> ExitCode {
+    let mut app_state = AppState::new(
+        args.dir,
+        false,
+        false,
+        false,
+        0.0
+    );
+    app_state.set_release_tag(args.tag);
+    app_state.set_release_source(args.source);
+    app_state.set_local_mas_archive(args.mas_archive);
+    let app_state: ThreadSafeState = Arc::new(Mutex::new(app_state));
+    let sender = ConsoleSender::new();
+    warn_interrupted_install(&app_state);
+
+    return match uninstaller::uninstall_game(&sender, &app_state) {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            sender.finish_progress();
+            eprintln!("Uninstallation failed: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Verifies the installation without the GUI, repairs it if asked
+fn run_verify(args: VerifyArgs) -> ExitCode {
+    let mut app_state = AppState::new(
+        args.dir,
+        false,
+        args.deluxe,
+        false,
+        0.0
+    );
+    app_state.set_release_tag(args.tag);
+    app_state.set_release_source(args.source);
+    app_state.set_local_mas_archive(args.mas_archive);
+    let app_state: ThreadSafeState = Arc::new(Mutex::new(app_state));
+    let sender = ConsoleSender::new();
+    warn_interrupted_install(&app_state);
+
+    let report = match repair::verify_game(&sender, &app_state) {
+        Ok(report) => report,
+        Err(e) => {
+            sender.finish_progress();
+            eprintln!("Verification failed: {e}");
+            return EXIT_FAILURE;
+        }
+    };
+    sender.finish_progress();
+
+    for file in report.missing.iter() {
+        println!("Missing: {file}");
+    }
+    for file in report.modified.iter() {
+        println!("Modified: {file}");
+    }
+    if report.is_ok() {
+        println!("All files are intact");
+        return EXIT_SUCCESS;
+    }
+    println!("{} missing and {} modified file(s)", report.missing.len(), report.modified.len());
+    if !args.repair {
+        println!("Run with --repair to fix them");
+        return EXIT_FAILURE;
+    }
+
+    return match repair::repair_game(&sender, &app_state, &report) {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            sender.finish_progress();
+            eprintln!("Repair failed: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Prints the tags of the releases available for installation
+fn run_releases(source: ReleaseSource) -> ExitCode {
+    let tags = installer::build_client().and_then(|client| installer::get_release_tags(&client, &source));
+    return match tags {
+        Ok(tags) => {
+            for tag in tags {
+                println!("{tag}");
+            }
+            EXIT_SUCCESS
+        },
+        Err(e) => {
+            eprintln!("Failed to fetch the list of releases: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Lists or prunes the download cache
+fn run_cache(command: CacheCommand) -> ExitCode {
+    let rv = match command {
+        CacheCommand::List => {
+            cache::list_cached_releases().map(
+                |releases| {
+                    println!("Cache directory: {}", cache::get_releases_dir().display());
+                    for release in releases {
+                        println!("{}\t{}", release.tag, utils::format_size(release.size));
+                    }
+                }
+            )
+        },
+        CacheCommand::Prune(keep) => {
+            cache::prune_cached_releases(keep).map(
+                |removed| {
+                    for release in removed {
+                        println!("Removed {} ({})", release.tag, utils::format_size(release.size));
+                    }
+                }
+            )
+        },
+        CacheCommand::Remove(tag) => {
+            cache::remove_cached_release(&tag).map(|_| println!("Removed {tag}"))
+        }
+    };
+
+    return match rv {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to access the cache: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Lists or restores the backups of the persistent and saves
+fn run_backups(command: BackupsCommand) -> ExitCode {
+    let rv = match command {
+        BackupsCommand::List => {
+            saves::list_backups().map(
+                |backups| {
+                    println!("Backups directory: {}", saves::get_backups_dir().display());
+                    for backup in backups {
+                        println!("{}\t{}", backup.name, utils::format_size(backup.size));
+                    }
+                }
+            )
+        },
+        BackupsCommand::Restore(name, dir) => {
+            let backup = match saves::find_backup(&name) {
+                Ok(Some(backup)) => backup,
+                Ok(None) => {
+                    eprintln!("Backup '{name}' doesn't exist");
+                    return EXIT_FAILURE;
+                },
+                Err(e) => {
+                    eprintln!("Failed to access the backups: {e}");
+                    return EXIT_FAILURE;
+                }
+            };
+            let app_state: ThreadSafeState = Arc::new(
+                Mutex::new(AppState::new(dir, false, false, false, 0.0))
+            );
+            let mas_dir = installer::get_destination(&app_state);
+            saves::restore_backup(&backup.path, &mas_dir).map(|_| println!("Restored {name}"))
+        }
+    };
+
+    return match rv {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            eprintln!("Failed to access the backups: {e}");
+            EXIT_FAILURE
+        }
+    };
+}
+
+/// Restores the files left by the installations that were interrupted in the given dir
+fn run_recover(dir: PathBuf) -> ExitCode {
+    // The transactions keep the staging dir in the selected dir, even on macOS,
+    // except for restoring backups, see find_interrupted_installs
+    let staging_dirs = installer::find_interrupted_installs(&dir);
+    if staging_dirs.is_empty() {
+        println!("Nothing to recover");
+        return EXIT_SUCCESS;
+    }
+
+    let mut failed: usize = 0;
+    for staging_dir in staging_dirs {
+        println!("Restoring files from {}...", staging_dir.display());
+        match transaction::recover_staging_dir(&staging_dir) {
+            Ok(count) => failed += count,
+            Err(e) => {
+                eprintln!("Failed to restore files from {}: {e}", staging_dir.display());
+                failed += 1;
+            }
+        };
+    }
+    if failed > 0 {
+        eprintln!("Failed to restore {failed} file(s)");
+        return EXIT_FAILURE;
+    }
+    println!("The original files have been restored");
+    return EXIT_SUCCESS;
+}
+
+/// Runs the CLI, returns the exit code for the process
+pub fn run() -> ExitCode {
+    let command = match parse_args(get_args()) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            eprintln!("Error: {e}\n\n{USAGE}");
+            return EXIT_USAGE;
+        }
+    };
+
+    return match command {
+        Command::Install(args) => run_install(args),
+        Command::Uninstall(args) => run_uninstall(args),
+        Command::Verify(args) => run_verify(args),
+        Command::Releases(source) => run_releases(source),
+        Command::Cache(command) => run_cache(command),
+        Command::Backups(command) => run_backups(command),
+        Command::Recover(dir) => run_recover(dir),
+        Command::Help => {
+            println!("{USAGE}");
+            EXIT_SUCCESS
+        },
+        Command::Version => {
+            println!("{}", crate::VERSION.unwrap_or(crate::DEF_VERSION));
+            EXIT_SUCCESS
+        }
+    };
+}
@@ -0,0 +1,253 @@
+/// The module that tells the game copies MAS can't be installed into:
+/// DDLC Plus and DDLC with another mod
+/// We look at the files the game has, the archives mods replace are checked by their header
+
+use std::{
+    fs::{read_dir, File},
+    io::Read,
+    path::Path
+};
+
+
+/// The files only the Unity builds of DDLC Plus have
+const DDLC_PLUS_FILES: [&str; 5] = [
+    "UnityPlayer.dll",
+    "GameAssembly.dll",
+    "UnityCrashHandler64.exe",
+    "Contents/Frameworks/UnityPlayer.dylib",
+    "Contents/Resources/Data/globalgamemanagers"
+];
+/// Unity keeps the game data in <game name>_Data
+const UNITY_DATA_DIR_SUFFIX: &str = "_Data";
+const UNITY_DATA_MARKER: &str = "globalgamemanagers";
+
+/// The archives vanilla DDLC has in the game dir
+const VANILLA_ARCHIVES: [&str; 4] = ["audio.rpa", "fonts.rpa", "images.rpa", "scripts.rpa"];
+/// The archives mods usually replace
+const CHECKED_ARCHIVES: [&str; 2] = ["scripts.rpa", "images.rpa"];
+/// The header of the archives made by the Ren'Py DDLC was built with
+const VANILLA_ARCHIVE_HEADER: &[u8] = b"RPA-3.0 ";
+/// MAS installed without a manifest and a version we can read still has this
+const MAS_MARKER: &str = "game/definitions.rpyc";
+/// The dir the DDLC mod template keeps the mod files in
+const MOD_ASSETS_DIR: &str = "mod_assets";
+const SCRIPT_EXTS: [&str; 2] = ["rpy", "rpyc"];
+
+/// The number of files to show in the explanation
+const MAX_SHOWN_FILES: usize = 3;
+
+
+/// The reason MAS can't be installed into a game copy
+#[derive(Debug)]
+pub enum Incompatibility {
+    /// The Unity remake, contains the files that gave it away
+    DdlcPlus(Vec<String>),
+    /// Another mod is installed, contains the files that gave it away
+    OtherMod(Vec<String>),
+    /// The game archives were replaced or removed, contains the archives
+    ModifiedArchives(Vec<String>)
+}
+
+impl Incompatibility {
+    /// Returns true if MAS can't work in this copy no matter what
+    pub fn is_fatal(&self) -> bool {
+        return matches!(self, Self::DdlcPlus(_));
+    }
+
+    /// Returns a short reason for the dir checklist
+    pub fn get_summary(&self) -> &'static str {
+        return match self {
+            Self::DdlcPlus(_) => "This is DDLC Plus, MAS needs the original DDLC",
+            Self::OtherMod(_) => "Another mod is installed",
+            Self::ModifiedArchives(_) => "The game archives aren't vanilla"
+        };
+    }
+
+    /// Returns the explanation for the user
+    pub fn get_explanation(&self) -> String {
+        return match self {
+            Self::DdlcPlus(files) => format!(
+                "This folder has Doki Doki Literature Club Plus ({}).\n\
+                DDLC Plus is a Unity remake, MAS is a mod for the original Ren'Py game\n\
+                and can't work with it. The original DDLC is free at ddlc.moe and on Steam.",
+                format_files(files)
+            ),
+            Self::OtherMod(files) => format!(
+                "This copy of DDLC already has another mod ({}).\n\
+                MAS replaces the game scripts and won't work together with other mods,\n\
+                install it into a clean copy of DDLC.",
+                format_files(files)
+            ),
+            Self::ModifiedArchives(archives) => format!(
+                "The game archives of this copy were replaced or removed ({}),\n\
+                probably by another mod. MAS needs the original archives,\n\
+                install it into a clean copy of DDLC.",
+                format_files(archives)
+            )
+        };
+    }
+}
+
+
+/// Formats the first few files for the explanation
+fn format_files(files: &[String]) -> String {
+    let mut rv = files.iter()
+        .take(MAX_SHOWN_FILES)
+        .map(|file| file.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ");
+    if files.len() > MAX_SHOWN_FILES {
+        rv.push_str(&format!(" and {} more", files.len() - MAX_SHOWN_FILES));
+    }
+    return rv;
+}
+
+/// Returns the names of the items in the dir, an empty vec if we can't read it
+fn get_item_names(dir: &Path) -> Vec<String> {
+    let content = match read_dir(dir) {
+        Ok(content) => content,
+        Err(_) => return Vec::new()
+    };
+    let mut names: Vec<String> = content
+        .filter_map(|item| item.ok())
+        .filter_map(|item| item.file_name().into_string().ok())
+        .collect();
+    names.sort();
+    return names;
+}
+
+/// Returns the DDLC Plus files in the dir
+fn find_ddlc_plus_files(dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = DDLC_PLUS_FILES.iter()
+        .filter(|file| dir.join(file).is_file())
+        .map(|file| file.to_string())
+        .collect();
+    files.extend(
+        get_item_names(dir).into_iter()
+            .filter(|name| name.ends_with(UNITY_DATA_DIR_SUFFIX))
+            .filter(|name| dir.join(name).join(UNITY_DATA_MARKER).is_file())
+    );
+    return files;
+}
+
+/// Returns the files in the game dir vanilla DDLC doesn't have
+fn find_mod_files(game_dir: &Path) -> Vec<String> {
+    let mut files = Vec::new();
+    for name in get_item_names(game_dir) {
+        let path = game_dir.join(&name);
+        let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        // Vanilla DDLC keeps all scripts in the archives
+        let is_script = SCRIPT_EXTS.contains(&ext);
+        let is_extra_archive = ext == "rpa" && !VANILLA_ARCHIVES.contains(&name.as_str());
+        let is_mod_assets = name == MOD_ASSETS_DIR && path.is_dir();
+        if is_script || is_extra_archive || is_mod_assets {
+            files.push(format!("game/{name}"));
+        }
+    }
+    return files;
+}
+
+/// Checks if the archive was made by the Ren'Py DDLC was built with
+/// Repacked or missing archives fail the check, an archive with the same header but
+/// different content passes, the header tells most repacks without reading the whole archive
+fn has_vanilla_header(path: &Path) -> bool {
+    let mut header = [0; VANILLA_ARCHIVE_HEADER.len()];
+    return File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .map_or(false, |_| header == VANILLA_ARCHIVE_HEADER);
+}
+
+/// Returns the archives that are missing or weren't made by the Ren'Py DDLC was built with
+fn find_modified_archives(game_dir: &Path) -> Vec<String> {
+    return CHECKED_ARCHIVES.iter()
+        .filter(|name| !has_vanilla_header(&game_dir.join(name)))
+        .map(|name| format!("game/{name}"))
+        .collect();
+}
+
+/// Checks if MAS can be installed into the given game dir (inside the bundle on macOS)
+/// An existing MAS installation is fine, it's upgraded,
+/// so set has_mas to skip the checks for other mods
+pub fn check_compatibility(game_dir: &Path, has_mas: bool) -> Option<Incompatibility> {
+    let ddlc_plus_files = find_ddlc_plus_files(game_dir);
+    if !ddlc_plus_files.is_empty() {
+        return Some(Incompatibility::DdlcPlus(ddlc_plus_files));
+    }
+
+    // The checks below are for the DDLC files, if it's not DDLC, it's not our business
+    if has_mas || game_dir.join(MAS_MARKER).is_file() || !game_dir.join("game").is_dir() {
+        return None;
+    }
+
+    let mod_files = find_mod_files(&game_dir.join("game"));
+    if !mod_files.is_empty() {
+        return Some(Incompatibility::OtherMod(mod_files));
+    }
+
+    let modified_archives = find_modified_archives(&game_dir.join("game"));
+    if !modified_archives.is_empty() {
+        return Some(Incompatibility::ModifiedArchives(modified_archives));
+    }
+
+    return None;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Creates a DDLC game dir with vanilla looking archives
+    fn create_game_dir() -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("game")).unwrap();
+        for name in VANILLA_ARCHIVES {
+            fs::write(root.path().join("game").join(name), b"RPA-3.0 0000000000000000 42424242\n").unwrap();
+        }
+        return root;
+    }
+
+    #[test]
+    fn test_vanilla_copy() {
+        let root = create_game_dir();
+        assert!(check_compatibility(root.path(), false).is_none());
+    }
+
+    #[test]
+    fn test_tampered_archive() {
+        let root = create_game_dir();
+        fs::write(root.path().join("game/scripts.rpa"), b"RPA-2.0 0000000000000000\n").unwrap();
+        match check_compatibility(root.path(), false) {
+            Some(Incompatibility::ModifiedArchives(archives)) => assert_eq!(archives, vec!["game/scripts.rpa"]),
+            rv => panic!("expected ModifiedArchives, got {rv:?}")
+        };
+    }
+
+    #[test]
+    fn test_missing_archive() {
+        let root = create_game_dir();
+        fs::remove_file(root.path().join("game/images.rpa")).unwrap();
+        match check_compatibility(root.path(), false) {
+            Some(Incompatibility::ModifiedArchives(archives)) => assert_eq!(archives, vec!["game/images.rpa"]),
+            rv => panic!("expected ModifiedArchives, got {rv:?}")
+        };
+    }
+
+    #[test]
+    fn test_other_mod() {
+        let root = create_game_dir();
+        fs::create_dir(root.path().join("game/mod_assets")).unwrap();
+        fs::write(root.path().join("game/script.rpyc"), b"").unwrap();
+        assert!(matches!(check_compatibility(root.path(), false), Some(Incompatibility::OtherMod(_))));
+        // MAS upgrades are fine
+        assert!(check_compatibility(root.path(), true).is_none());
+    }
+
+    #[test]
+    fn test_ddlc_plus() {
+        let root = create_game_dir();
+        fs::write(root.path().join("UnityPlayer.dll"), b"").unwrap();
+        assert!(matches!(check_compatibility(root.path(), true), Some(Incompatibility::DdlcPlus(_))));
+    }
+}
@@ -0,0 +1,327 @@
+/// The module that looks for DDLC installations in the usual places:
+/// Steam libraries, the itch.io app and the user's downloads
+
+use std::{
+    env,
+    fs::{self, read_dir},
+    path::{Path, PathBuf},
+    thread
+};
+
+use fltk::app::Sender;
+
+use crate::{
+    app::{
+        state::ThreadSafeState,
+        Message
+    },
+    utils
+};
+
+
+/// The name of the DDLC dir in the Steam libraries
+const STEAM_DDLC_DIR_NAME: &str = "Doki Doki Literature Club";
+/// The bundle DDLC is shipped in on macOS
+const MACOS_BUNDLE_NAME: &str = "DDLC.app";
+/// The dirs with these in the name might have DDLC (e.g. DDLC-1.1.1-pc)
+const DDLC_DIR_NAME_HINTS: [&str; 2] = ["ddlc", "doki"];
+
+
+/// Returns the home dir of the user
+fn get_home_dir() -> Option<PathBuf> {
+    let var = match env::consts::OS {
+        "windows" => "USERPROFILE",
+        _ => "HOME"
+    };
+    return env::var_os(var).map(PathBuf::from);
+}
+
+/// Returns the dirs Steam might be installed in
+fn get_steam_roots() -> Vec<PathBuf> {
+    let mut roots = Vec::new();
+    match env::consts::OS {
+        "windows" => {
+            for var in ["ProgramFiles(x86)", "ProgramFiles"] {
+                if let Some(dir) = env::var_os(var) {
+                    roots.push(PathBuf::from(dir).join("Steam"));
+                }
+            }
+        },
+        "macos" => {
+            if let Some(home) = get_home_dir() {
+                roots.push(home.join("Library/Application Support/Steam"));
+            }
+        },
+        _ => {
+            if let Some(home) = get_home_dir() {
+                roots.push(home.join(".steam/steam"));
+                roots.push(home.join(".steam/root"));
+                roots.push(home.join(".local/share/Steam"));
+                // Flatpak
+                roots.push(home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"));
+                roots.push(home.join(".var/app/com.valvesoftware.Steam/data/Steam"));
+                // Snap
+                roots.push(home.join("snap/steam/common/.local/share/Steam"));
+            }
+        }
+    };
+    return roots;
+}
+
+/// Returns the quoted strings of a Valve KeyValues (vdf) file,
+/// the braces are returned as is, so we can tell the sections
+fn tokenize_vdf(content: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut token = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        // Windows paths have the backslashes escaped
+                        '\\' => token.extend(chars.next()),
+                        _ => token.push(c)
+                    };
+                }
+                tokens.push(token);
+            },
+            '{' | '}' => tokens.push(c.to_string()),
+            _ => {}
+        };
+    }
+    return tokens;
+}
+
+/// Returns the library paths from the content of libraryfolders.vdf
+/// The new format has the path in a section: "0" { "path" "/some/path" ... },
+/// the old one has it right after the index: "1" "/some/path"
+fn parse_library_folders(content: &str) -> Vec<PathBuf> {
+    let tokens = tokenize_vdf(content);
+    let mut paths = Vec::new();
+    let mut depth: usize = 0;
+
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "{" => depth += 1,
+            "}" => depth = depth.saturating_sub(1),
+            key => {
+                // A section starts, the brace is handled on the next iteration
+                if let Some(value) = tokens.get(i+1).filter(|value| *value != "{" && *value != "}") {
+                    let is_old_index = depth == 1 && key.chars().all(|c| c.is_ascii_digit());
+                    let is_path = depth == 2 && key == "path";
+                    if is_old_index || is_path {
+                        paths.push(PathBuf::from(value));
+                    }
+                    i += 1;
+                }
+            }
+        };
+        i += 1;
+    }
+    return paths;
+}
+
+/// Returns the Steam libraries, the Steam dir itself is one of them
+fn get_steam_libraries() -> Vec<PathBuf> {
+    let mut libraries = Vec::new();
+    for root in get_steam_roots() {
+        if !root.is_dir() {
+            continue;
+        }
+        for vdf_path in [root.join("steamapps/libraryfolders.vdf"), root.join("config/libraryfolders.vdf")] {
+            if let Ok(content) = fs::read_to_string(&vdf_path) {
+                libraries.extend(parse_library_folders(&content));
+            }
+        }
+        libraries.push(root);
+    }
+    return libraries;
+}
+
+/// Returns the dirs the itch.io app installs games into
+fn get_itch_apps_dirs() -> Vec<PathBuf> {
+    let base_dir = match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(PathBuf::from),
+        "macos" => get_home_dir().map(|home| home.join("Library/Application Support")),
+        _ => get_home_dir().map(|home| home.join(".config"))
+    };
+    return base_dir.map(|dir| vec![dir.join("itch/apps")]).unwrap_or_default();
+}
+
+/// Returns the dirs users usually unpack downloaded games into
+fn get_common_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(home) = get_home_dir() {
+        dirs.push(home.join("Downloads"));
+        dirs.push(home.join("Desktop"));
+        dirs.push(home.join("Games"));
+        dirs.push(home);
+    }
+    return dirs;
+}
+
+/// Returns the subdirs of the given dir, an empty vec if we can't read it
+fn get_subdirs(dir: &Path) -> Vec<PathBuf> {
+    let content = match read_dir(dir) {
+        Ok(content) => content,
+        Err(_) => return Vec::new()
+    };
+    return content
+        .filter_map(|item| item.ok())
+        .map(|item| item.path())
+        .filter(|path| path.is_dir())
+        .collect();
+}
+
+/// Checks if the dir name hints it has DDLC
+fn has_ddlc_name(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_lowercase(),
+        None => return false
+    };
+    return DDLC_DIR_NAME_HINTS.iter().any(|hint| name.contains(hint));
+}
+
+/// Returns the dir and its macOS bundle, DDLC can be in either
+fn with_bundle(path: PathBuf) -> Vec<PathBuf> {
+    let bundle = path.join(MACOS_BUNDLE_NAME);
+    return vec![path, bundle];
+}
+
+/// Returns the dirs that might have DDLC, they are checked later
+fn get_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    for library in get_steam_libraries() {
+        candidates.extend(with_bundle(library.join("steamapps/common").join(STEAM_DDLC_DIR_NAME)));
+    }
+
+    // Each game has its own dir, the game might be in a subdir with the version
+    for apps_dir in get_itch_apps_dirs() {
+        for app_dir in get_subdirs(&apps_dir) {
+            for subdir in get_subdirs(&app_dir) {
+                candidates.extend(with_bundle(subdir));
+            }
+            candidates.extend(with_bundle(app_dir));
+        }
+    }
+
+    // These have all sorts of things, only look into the dirs named like DDLC
+    for dir in get_common_dirs() {
+        for subdir in get_subdirs(&dir).into_iter().filter(|path| has_ddlc_name(path)) {
+            for inner_dir in get_subdirs(&subdir) {
+                candidates.extend(with_bundle(inner_dir));
+            }
+            candidates.extend(with_bundle(subdir));
+        }
+    }
+
+    return candidates;
+}
+
+/// Looks for DDLC installations, returns the valid ones without duplicates
+/// The order is Steam, itch.io and then the common dirs
+/// The dirs are only read, we don't want to leave traces in every dir we look at
+pub fn find_ddlc_dirs() -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = Vec::new();
+    let mut seen = Vec::new();
+
+    for candidate in get_candidates() {
+        // is_valid_ddlc_dir allows the dirs it can't read, we only want the ones we're sure about
+        if read_dir(&candidate).is_err() || !utils::is_valid_ddlc_dir(&candidate) {
+            continue;
+        }
+        // Steam roots are often symlinks to each other
+        let real_path = fs::canonicalize(&candidate).unwrap_or_else(|_| candidate.clone());
+        if seen.contains(&real_path) {
+            continue;
+        }
+        seen.push(real_path);
+        found.push(candidate);
+    }
+    return found;
+}
+
+/// Looks for DDLC installations in a thread, the dirs are saved into the app state
+/// Sends DdlcDirsFound once done
+pub fn find_ddlc_dirs_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState
+) -> thread::JoinHandle<()> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || {
+            let dirs = find_ddlc_dirs();
+            app_state.lock().unwrap().set_found_ddlc_dirs(dirs);
+            sender.send(Message::DdlcDirsFound);
+        }
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize_vdf() {
+        let tokens = tokenize_vdf("\"libraryfolders\"\n{\n\t\"path\"\t\t\"C:\\\\Games\\\\Steam\"\n}");
+        assert_eq!(tokens, vec!["libraryfolders", "{", "path", "C:\\Games\\Steam", "}"]);
+    }
+
+    #[test]
+    fn test_parse_library_folders_new_format() {
+        let content = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"698780"		"1234"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+		"label"		"games"
+	}
+}
+"#;
+        assert_eq!(
+            parse_library_folders(content),
+            vec![PathBuf::from("/home/user/.local/share/Steam"), PathBuf::from("/mnt/games/SteamLibrary")]
+        );
+    }
+
+    #[test]
+    fn test_parse_library_folders_old_format() {
+        let content = r#"
+"LibraryFolders"
+{
+	"TimeNextStatsReport"		"1234567890"
+	"ContentStatsID"		"-1234567890"
+	"1"		"D:\\SteamLibrary"
+	"2"		"E:\\Games\\Steam"
+}
+"#;
+        assert_eq!(
+            parse_library_folders(content),
+            vec![PathBuf::from("D:\\SteamLibrary"), PathBuf::from("E:\\Games\\Steam")]
+        );
+    }
+
+    #[test]
+    fn test_parse_library_folders_invalid() {
+        assert!(parse_library_folders("").is_empty());
+        assert!(parse_library_folders("\"libraryfolders\" { \"0\" {").is_empty());
+    }
+}
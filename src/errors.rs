@@ -2,6 +2,7 @@
 use std::io::Error as IOError;
 use std::error::Error as StdError;
 use std::fmt;
+use std::path::{Path, PathBuf};
 
 use zip::result::ZipError;
 use reqwest::{
@@ -17,6 +18,20 @@ use rodio::{
 };
 
 
+/// Writes the message for the errors about disk space
+fn write_not_enough_space(f: &mut fmt::Formatter<'_>, dir: &Path, required: u64, available: u64) -> fmt::Result {
+    return write!(
+        f,
+        "not enough disk space in '{}': {} ({} bytes) required, {} ({} bytes) available",
+        dir.display(),
+        crate::utils::format_size(required),
+        required,
+        crate::utils::format_size(available),
+        available
+    );
+}
+
+
 /// Error type repesenting an error occured during downloading
 #[derive(Debug)]
 pub enum DownloadError {
@@ -28,7 +43,13 @@ pub enum DownloadError {
     /// while downloading the assets
     InvalidStatusCode(StatusCode),
     /// General IO failure, couldn't write/read
-    IOError(IOError)
+    IOError(IOError),
+    /// The downloaded data doesn't match the published checksum,
+    /// contains the expected and the actual SHA-256
+    ChecksumMismatch(String, String),
+    /// Not enough space to download the assets,
+    /// contains the dir, the required and the available space
+    NotEnoughSpace(PathBuf, u64, u64)
 }
 
 impl From<ReqError> for DownloadError {
@@ -66,6 +87,12 @@ impl fmt::Display for DownloadError {
             },
             Self::IOError(err) => {
                 write!(f, "failed to read/write data: {}", err)
+            },
+            Self::ChecksumMismatch(expected, actual) => {
+                write!(f, "downloaded data is corrupted: expected SHA-256 {}, got {}", expected, actual)
+            },
+            Self::NotEnoughSpace(dir, required, available) => {
+                write_not_enough_space(f, dir, *required, *available)
             }
         };
     }
@@ -80,7 +107,16 @@ pub enum ExtractionError {
     /// Unsafe file path in the archive, possible attack?
     UnsafeFilepath(String),
     /// I/O error
-    IOError(IOError)
+    IOError(IOError),
+    /// Not enough space to extract the archive,
+    /// contains the dir, the required and the available space
+    NotEnoughSpace(PathBuf, u64, u64),
+    /// The archive exceeds the extraction limits, possible zip bomb?
+    /// contains the description of the exceeded limit
+    LimitExceeded(String),
+    /// The data of an entry is corrupted (e.g. CRC mismatch),
+    /// contains the name of the entry
+    CorruptedEntry(String, IOError)
 }
 
 impl From<ZipError> for ExtractionError {
@@ -99,6 +135,7 @@ impl StdError for ExtractionError {
         return match self {
             Self::ArchiveError(og_err) => Some(og_err),
             Self::IOError(og_err) => Some(og_err),
+            Self::CorruptedEntry(_, og_err) => Some(og_err),
             _ => None
         };
     }
@@ -113,6 +150,64 @@ impl fmt::Display for ExtractionError {
             Self::UnsafeFilepath(_) => {
                 write!(f, "found unsafe filepath in archive")
             },
+            Self::IOError(err) => {
+                write!(f, "failed to read/write data: {}", err)
+            },
+            Self::NotEnoughSpace(dir, required, available) => {
+                write_not_enough_space(f, dir, *required, *available)
+            },
+            Self::LimitExceeded(info) => {
+                write!(f, "archive exceeds the extraction limits: {}", info)
+            },
+            Self::CorruptedEntry(name, err) => {
+                write!(f, "archive is corrupted, failed to read '{}': {}", name, err)
+            }
+        };
+    }
+}
+
+
+/// Error type repesenting an error occured while backing up or restoring saves
+#[derive(Debug)]
+pub enum BackupError {
+    /// An issue with the backup archive
+    ArchiveError(ZipError),
+    /// Unsafe file path in the backup archive
+    UnsafeFilepath(String),
+    /// I/O error
+    IOError(IOError)
+}
+
+impl From<ZipError> for BackupError {
+    fn from(err: ZipError) -> Self {
+        return Self::ArchiveError(err);
+    }
+}
+impl From<IOError> for BackupError {
+    fn from(err: IOError) -> Self {
+        return Self::IOError(err);
+    }
+}
+
+impl StdError for BackupError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        return match self {
+            Self::ArchiveError(og_err) => Some(og_err),
+            Self::IOError(og_err) => Some(og_err),
+            _ => None
+        };
+    }
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::ArchiveError(err) => {
+                write!(f, "backup archive issue: {}", err)
+            },
+            Self::UnsafeFilepath(path) => {
+                write!(f, "found unsafe filepath in backup: {}", path)
+            },
             Self::IOError(err) => {
                 write!(f, "failed to read/write data: {}", err)
             }
@@ -136,7 +231,16 @@ pub enum InstallError {
     /// General IO failure, couldn't write/read
     IOError(IOError),
     /// Error occured during extraction
-    ExtractionError(ExtractionError)
+    ExtractionError(ExtractionError),
+    /// There's no release with the given tag
+    ReleaseNotFound(String),
+    /// Offline installation is missing a required archive
+    MissingLocalArchive(&'static str),
+    /// The release is older than the installed one and downgrades aren't allowed,
+    /// contains the installed and the requested versions
+    Downgrade(String, String),
+    /// Failed to back up the saves
+    BackupError(BackupError)
 }
 
 impl From<SerdeError> for InstallError {
@@ -164,6 +268,11 @@ impl From<ExtractionError> for InstallError{
         return Self::ExtractionError(err);
     }
 }
+impl From<BackupError> for InstallError {
+    fn from(err: BackupError) -> Self {
+        return Self::BackupError(err);
+    }
+}
 
 impl StdError for InstallError {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
@@ -173,6 +282,7 @@ impl StdError for InstallError {
             Self::RequestError(og_err) => Some(og_err),
             Self::IOError(og_err) => Some(og_err),
             Self::ExtractionError(og_err) => Some(og_err),
+            Self::BackupError(og_err) => Some(og_err),
             _ => None
         };
     }
@@ -198,6 +308,68 @@ impl fmt::Display for InstallError {
             },
             Self::ExtractionError(err) => {
                 write!(f, "extraction failed: {}", err)
+            },
+            Self::ReleaseNotFound(tag) => {
+                write!(f, "release '{}' doesn't exist", tag)
+            },
+            Self::MissingLocalArchive(name) => {
+                write!(f, "offline installation requires a local {} archive", name)
+            },
+            Self::Downgrade(installed, requested) => {
+                write!(f, "installed version {} is newer than {}, downgrading isn't allowed", installed, requested)
+            },
+            Self::BackupError(err) => {
+                write!(f, "failed to back up saves: {}", err)
+            }
+        };
+    }
+}
+
+
+/// Error type representing invalid command line arguments
+#[derive(Debug)]
+pub enum ArgsError {
+    /// The command isn't known
+    UnknownCommand(String),
+    /// The command requires a subcommand, but none was given
+    MissingCommand(String),
+    /// The option isn't known
+    UnknownOption(String),
+    /// The option requires a value, but none was given
+    MissingValue(String),
+    /// The given path isn't a directory
+    InvalidDir(String),
+    /// The option got a value it doesn't accept
+    InvalidValue(String, String),
+    /// The options can't be used together
+    ConflictingOptions(String, String)
+}
+
+impl StdError for ArgsError {}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            Self::UnknownCommand(cmd) => {
+                write!(f, "unknown command '{cmd}'")
+            },
+            Self::MissingCommand(cmd) => {
+                write!(f, "command '{cmd}' requires a subcommand")
+            },
+            Self::UnknownOption(opt) => {
+                write!(f, "unknown option '{opt}'")
+            },
+            Self::MissingValue(opt) => {
+                write!(f, "option '{opt}' requires a value")
+            },
+            Self::InvalidDir(path) => {
+                write!(f, "'{path}' is not a directory")
+            },
+            Self::InvalidValue(opt, value) => {
+                write!(f, "invalid value '{value}' for option '{opt}'")
+            },
+            Self::ConflictingOptions(opt, other_opt) => {
+                write!(f, "options '{opt}' and '{other_opt}' can't be used together")
             }
         };
     }
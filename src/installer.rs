@@ -2,13 +2,13 @@
 
 use std::{
     env,
-    path::Path,
-    fs::{File, create_dir_all, read_dir, remove_file, remove_dir_all},
-    io,
-    cmp::min,
-    collections::HashMap,
+    path::{Path, PathBuf},
+    fs::{File, read_dir},
+    io::{self, Read, Seek, SeekFrom, Write},
+    cmp::{min, Ordering},
+    collections::{BTreeSet, HashMap, HashSet},
     thread,
-    time::Duration
+    time::{Duration, Instant}
 };
 
 use fltk::app::{
@@ -17,16 +17,28 @@ use fltk::app::{
 
 use ::reqwest::{
     blocking as reqwest,
-    header as headers
+    header as headers,
+    StatusCode
 };
 
 use serde::{Serialize, Deserialize};
 
+use sha2::{Sha256, Digest};
+
+use crc32fast::Hasher as Crc32Hasher;
+
 use zip::ZipArchive;
 
 use crate::{
+    cache,
+    saves,
+    manifest::{self, Manifest, ManifestEntry, Variant},
+    transaction::{self, Transaction},
+    uninstaller,
+    utils,
+    version,
     app::{
-        state::ThreadSafeState,
+        state::{AppState, ThreadSafeState},
         Message
     },
     errors::{
@@ -38,6 +50,20 @@ use crate::{
 
 
 const PAUSE_DURATION: Duration = Duration::from_millis(200);
+// The releases are listed 100 per page, no need to go thru thousands of them
+const MAX_RELEASE_PAGES: usize = 10;
+// How often we report the download stats
+const DOWNLOAD_STATS_INTERVAL: Duration = Duration::from_millis(500);
+// The weight of the latest rate sample, the rest comes from the previous rate
+const DOWNLOAD_RATE_SMOOTHING: f64 = 0.3;
+
+// Extraction limits, the archives can come from mirrors and the user,
+// the real ones are far below these
+const MAX_ARCHIVE_ENTRIES: usize = 100_000;
+const MAX_ARCHIVE_UNCOMPRESSED_SIZE: ContentSize = 16*1024*1024*1024;
+const MAX_ENTRY_COMPRESSION_RATIO: ContentSize = 200;
+// Small entries (e.g. empty scripts) can have any ratio
+const MIN_ENTRY_SIZE_FOR_RATIO: ContentSize = 1024*1024;
 
 
 pub type InstallResult = Result<(), InstallError>;
@@ -51,7 +77,9 @@ struct ReleaseData {
     name: String,
     def_ver_asset: GHAsset,
     dlx_ver_asset: GHAsset,
-    spr_asset: GHAsset
+    spr_asset: GHAsset,
+    // Map of asset names to their SHA-256 from the checksums asset
+    checksums: HashMap<String, String>
 }
 
 impl ReleaseData {
@@ -61,9 +89,17 @@ impl ReleaseData {
         name: String,
         def_ver_asset: GHAsset,
         dlx_ver_asset: GHAsset,
-        spr_asset: GHAsset
+        spr_asset: GHAsset,
+        checksums: HashMap<String, String>
     ) -> Self {
-        return Self { version, name, def_ver_asset, dlx_ver_asset, spr_asset };
+        return Self { version, name, def_ver_asset, dlx_ver_asset, spr_asset, checksums };
+    }
+
+    /// Returns the expected SHA-256 of the given asset if the release publishes it
+    pub fn get_expected_sha256<'a>(&'a self, asset: &'a GHAsset) -> Option<&'a str> {
+        return asset.get_sha256().or_else(
+            || self.checksums.get(&asset.name).map(String::as_str)
+        );
     }
 }
 
@@ -72,20 +108,37 @@ impl ReleaseData {
 struct GHAsset {
     name: String,
     size: ContentSize,
-    browser_download_url: String
+    browser_download_url: String,
+    // Not all releases have it, format: "sha256:<hex>"
+    #[serde(default)]
+    digest: Option<String>
 }
 
 impl GHAsset {
-    /// Check if this asset is valid
-    pub fn is_valid(&self) -> bool {
+    /// Returns the SHA-256 from the digest of this asset if GitHub provided it
+    pub fn get_sha256(&self) -> Option<&str> {
+        return self.digest.as_deref()?.strip_prefix("sha256:");
+    }
+
+
+    /// Check if the download link of this asset is valid
+    /// Plain http links are only accepted if allowed (e.g. a local test server)
+    pub fn has_valid_url(&self, allow_http: bool) -> bool {
         return {
             !self.name.is_empty()
             && self.size != 0
             && !self.browser_download_url.is_empty()
-            && self.browser_download_url.starts_with("https://")
-            && self.browser_download_url.ends_with(".zip")
+            && (
+                self.browser_download_url.starts_with("https://")
+                || (allow_http && self.browser_download_url.starts_with("http://"))
+            )
         };
     }
+
+    /// Check if this asset is a valid archive
+    pub fn is_valid(&self, allow_http: bool) -> bool {
+        return self.has_valid_url(allow_http) && self.browser_download_url.ends_with(".zip");
+    }
 }
 
 /// Represents a GitHub release
@@ -93,7 +146,12 @@ impl GHAsset {
 struct GHRelease {
     tag_name: String,
     name: String,
-    assets: Vec<GHAsset>
+    assets: Vec<GHAsset>,
+    // /releases/latest skips these, so the list does too
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool
 }
 
 impl GHRelease {
@@ -108,6 +166,117 @@ impl GHRelease {
 }
 
 
+/// Trait for the types the installer can report its progress to,
+/// the GUI uses fltk channels, the CLI prints to the terminal
+pub trait MessageSender {
+    /// Sends a message to the receiver
+    fn send(&self, msg: Message);
+
+    /// Returns true if the receiver can ask the user (e.g. to select spritepacks),
+    /// the answer is written into the app state
+    fn is_interactive(&self) -> bool {
+        return false;
+    }
+}
+
+impl MessageSender for Sender<Message> {
+    fn send(&self, msg: Message) {
+        Sender::send(self, msg);
+    }
+
+    fn is_interactive(&self) -> bool {
+        return true;
+    }
+}
+
+
+/// Represents the place we get releases from,
+/// anything that speaks GitHub releases JSON will do
+#[derive(Debug, Clone)]
+pub struct ReleaseSource {
+    api_url: String,
+    repo: String
+}
+
+impl ReleaseSource {
+    /// Creates a new ReleaseSource
+    /// api_url - base URL of the API, e.g. https://api.github.com
+    /// repo - the repository slug, e.g. Monika-After-Story/MonikaModDev
+    pub fn new(api_url: &str, repo: &str) -> Self {
+        return Self {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            repo: repo.trim_matches('/').to_string()
+        };
+    }
+
+    /// Creates a ReleaseSource using the env vars, falls back to the defaults
+    pub fn from_env() -> Self {
+        let mut source = Self::default();
+
+        if let Ok(api_url) = env::var(crate::API_URL_ENV_VAR) {
+            match Self::is_valid_api_url(&api_url) {
+                true => source = Self::new(&api_url, &source.repo),
+                false => eprintln!("Ignoring invalid {}: '{}'", crate::API_URL_ENV_VAR, api_url)
+            };
+        }
+        if let Ok(repo) = env::var(crate::REPO_SLUG_ENV_VAR) {
+            match Self::is_valid_repo(&repo) {
+                true => source = Self::new(&source.api_url, &repo),
+                false => eprintln!("Ignoring invalid {}: '{}'", crate::REPO_SLUG_ENV_VAR, repo)
+            };
+        }
+
+        return source;
+    }
+
+    /// Checks if the given string can be used as the API URL
+    pub fn is_valid_api_url(api_url: &str) -> bool {
+        return api_url.starts_with("https://") || api_url.starts_with("http://");
+    }
+
+    /// Checks if the given string is a valid repository slug (owner/name)
+    pub fn is_valid_repo(repo: &str) -> bool {
+        let parts: Vec<&str> = repo.trim_matches('/').split('/').collect();
+        return parts.len() == 2 && parts.iter().all(|part| !part.is_empty());
+    }
+
+    /// Returns the API URL
+    pub fn get_api_url(&self) -> &str {
+        return &self.api_url;
+    }
+
+    /// Returns the repository slug
+    pub fn get_repo(&self) -> &str {
+        return &self.repo;
+    }
+
+    /// Returns true if we're allowed to download assets over plain http,
+    /// only when the API itself isn't using https
+    pub fn allows_http(&self) -> bool {
+        return !self.api_url.starts_with("https://");
+    }
+
+    /// Returns the URL of the releases endpoint
+    pub fn get_releases_url(&self) -> String {
+        return format!("{}/repos/{}/releases", self.api_url, self.repo);
+    }
+
+    /// Returns the URL of the changelog for the given release, or the latest one
+    pub fn get_changelog_url(&self, tag: Option<&str>) -> String {
+        return match tag {
+            Some(tag) => format!("https://github.com/{}/releases/tag/{}", self.repo, tag),
+            None => format!("https://github.com/{}/releases/latest", self.repo)
+        };
+    }
+}
+
+impl Default for ReleaseSource {
+    fn default() -> Self {
+        return Self::new(crate::DEF_API_URL, crate::DEF_REPO_SLUG);
+    }
+}
+
+
 /// Blocks the thread for PAUSE_DURATION seconds
 fn sleep() {
     thread::sleep(PAUSE_DURATION);
@@ -125,35 +294,131 @@ pub fn build_client() -> Result<reqwest::Client, InstallError> {
 }
 
 
-/// Requests release data from github
-fn get_release_data(client: &reqwest::Client) -> Result<ReleaseData, InstallError> {
-    let data = client.get(
-        format!(
-            "https://api.github.com/repos/{}/{}/releases/latest",
-            crate::ORG_NAME,
-            crate::REPO_NAME
-        )
-    ).send()?.bytes()?;
+/// Checks if the release has all the assets we need to install it
+fn has_required_assets(release: &GHRelease) -> bool {
+    return crate::ASSETS_NAMES_RE_MAP.values().all(
+        |re| release.assets.iter().any(|asset| re.is_match(&asset.name))
+    );
+}
+
+
+/// Returns the URL of the next page from the Link header of a paginated response
+/// The format is: <https://...?page=2>; rel="next", <https://...?page=5>; rel="last"
+fn get_next_page_url(resp: &reqwest::Response) -> Option<String> {
+    let link = resp.headers().get(headers::LINK)?.to_str().ok()?;
+    return link.split(',')
+        .find(|part| part.split(';').skip(1).any(|param| param.trim() == "rel=\"next\""))
+        .and_then(|part| part.split(';').next())
+        .map(|url| url.trim().trim_start_matches('<').trim_end_matches('>').to_string());
+}
+
+/// Returns the tags of the releases we can install, prereleases and drafts are skipped
+/// like /releases/latest does, so the first tag is the release we install by default
+fn get_installable_tags(releases: Vec<GHRelease>) -> impl Iterator<Item = String> {
+    return releases.into_iter()
+        .filter(|release| release.is_valid() && !release.prerelease && !release.draft && has_required_assets(release))
+        .map(|release| release.tag_name);
+}
+
+/// Requests the list of releases from the given source,
+/// returns the tags of the ones we can install, newest first (see get_installable_tags)
+/// The releases are paginated, we follow the pages up to MAX_RELEASE_PAGES
+pub fn get_release_tags(client: &reqwest::Client, source: &ReleaseSource) -> Result<Vec<String>, InstallError> {
+    let mut tags = Vec::new();
+    let mut url = Some(format!("{}?per_page=100", source.get_releases_url()));
+
+    for _ in 0..MAX_RELEASE_PAGES {
+        let page_url = match url {
+            Some(page_url) => page_url,
+            None => break
+        };
+        let resp = client.get(page_url).send()?;
+        let status_code = resp.status();
+        if !status_code.is_success() {
+            return Err(DownloadError::InvalidStatusCode(status_code).into());
+        }
+        url = get_next_page_url(&resp);
+
+        let releases: Vec<GHRelease> = serde_json::from_slice(&resp.bytes()?)?;
+        tags.extend(get_installable_tags(releases));
+    }
+    if url.is_some() {
+        eprintln!("Only the first {} pages of releases are listed", MAX_RELEASE_PAGES);
+    }
+
+    return Ok(tags);
+}
+
+/// Returns the tag of the installed release, e.g. v0.12.15 for the installed 0.12.15
+/// Uses the tags from the app state, requests them if we don't have them yet
+/// Returns None if we can't tell the installed version
+pub fn find_installed_tag(app_state: &ThreadSafeState, mas_dir: &Path) -> Result<Option<String>, InstallError> {
+    let installed = match version::get_installed_version(mas_dir) {
+        Some(installed) => installed,
+        None => return Ok(None)
+    };
+
+    let (source, mut tags) = {
+        let app_state = app_state.lock().unwrap();
+        (app_state.get_release_source().clone(), app_state.get_release_tags().clone())
+    };
+    if tags.is_empty() {
+        tags = get_release_tags(&build_client()?, &source)?;
+        app_state.lock().unwrap().set_release_tags(tags.clone());
+    }
+
+    return match version::find_tag(&tags, &installed) {
+        Some(tag) => Ok(Some(tag.clone())),
+        None => Err(InstallError::ReleaseNotFound(installed))
+    };
+}
+
 
-    let release: GHRelease = serde_json::from_slice(&data)?;
+/// Requests release data from the given source
+/// Uses the release with the given tag or the latest one if there's no tag
+fn get_release_data(
+    client: &reqwest::Client,
+    source: &ReleaseSource,
+    tag: Option<&str>
+) -> Result<ReleaseData, InstallError> {
+    let url = match tag {
+        Some(tag) => format!("{}/tags/{}", source.get_releases_url(), tag),
+        None => format!("{}/latest", source.get_releases_url())
+    };
+    let resp = client.get(url).send()?;
+    let status_code = resp.status();
+    if status_code == StatusCode::NOT_FOUND {
+        return Err(InstallError::ReleaseNotFound(tag.unwrap_or("latest").to_string()));
+    }
+    if !status_code.is_success() {
+        return Err(DownloadError::InvalidStatusCode(status_code).into());
+    }
+
+    let release: GHRelease = serde_json::from_slice(&resp.bytes()?)?;
     if !release.is_valid() {
         eprintln!("Release '{:?}' is invalid", release);
-        return Err(InstallError::CorruptedJSON("Latest release is invalid"));
+        return Err(InstallError::CorruptedJSON("Release is invalid"));
     }
 
     // Create a map of the assets we need
     let mut assets_map = HashMap::new();
+    let mut checksums_asset = None;
 
     // Search thru all the available assets and find the ones we need
     'outer_loop: for asset in release.assets {
-        // Quit early if we filled the map
-        if assets_map.len() == crate::ASSETS_NAMES_RE_MAP.len() {
-            break;
+        // The checksums are optional, but we want them if they're there
+        if checksums_asset.is_none() && crate::CHECKSUMS_ASSET_NAME_RE.is_match(&asset.name) {
+            if !asset.has_valid_url(source.allows_http()) {
+                eprintln!("Asset '{}' is invalid", asset.name);
+                return Err(InstallError::CorruptedJSON("Found the checksums asset, but it's invalid"));
+            }
+            checksums_asset = Some(asset);
+            continue;
         }
         // Use regex to find the assets
         for (k, v) in crate::ASSETS_NAMES_RE_MAP.iter() {
             if !assets_map.contains_key(k) && v.is_match(&asset.name) {
-                if !asset.is_valid() {
+                if !asset.is_valid(source.allows_http()) {
                     eprintln!("Asset '{}' is invalid", asset.name);
                     return Err(InstallError::CorruptedJSON("Found a required asset, but it's invalid"));
                 }
@@ -168,21 +433,58 @@ fn get_release_data(client: &reqwest::Client) -> Result<ReleaseData, InstallErro
         return Err(InstallError::CorruptedJSON("An asset is missing from the release"));
     }
 
+    let checksums = match checksums_asset {
+        Some(asset) => get_checksums(client, &asset)?,
+        None => HashMap::new()
+    };
+
     let data = ReleaseData::new(
         release.tag_name,
         release.name,
         assets_map.remove("def_ver").unwrap(),
         assets_map.remove("dlx_ver").unwrap(),
-        assets_map.remove("spr").unwrap()
+        assets_map.remove("spr").unwrap(),
+        checksums
     );
     return Ok(data);
 }
 
 
+/// Downloads and parses the checksums asset
+/// The expected format is the one of sha256sum: "<hex>  <file name>" per line
+fn get_checksums(client: &reqwest::Client, asset: &GHAsset) -> Result<HashMap<String, String>, DownloadError> {
+    let resp = client.get(&asset.browser_download_url).send()?;
+    let status_code = resp.status();
+    if !status_code.is_success() {
+        return Err(DownloadError::InvalidStatusCode(status_code));
+    }
+
+    return Ok(parse_checksums(&resp.text()?));
+}
+
+/// Parses the checksums in the format of sha256sum, returns a map of file names to hashes
+fn parse_checksums(text: &str) -> HashMap<String, String> {
+    let mut checksums = HashMap::new();
+    for line in text.lines() {
+        if let Some((hash, name)) = line.trim().split_once(char::is_whitespace) {
+            // Skip anything that isn't a SHA-256, e.g. comments
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+            // sha256sum marks binary mode with an asterisk
+            let name = name.trim_start().trim_start_matches('*');
+            checksums.insert(name.to_string(), hash.to_lowercase());
+        }
+    }
+    return checksums;
+}
+
+
 /// Prepares a DDLC directory (unlinks some files/folders at the given path)
+/// The files are removed via the transaction, so they can be restored
 /// This function is "best-effort" and will ignore errors
 /// TPG, I know you will love these nested ifs
-fn prepare_ddlc_dir(path: &Path, remove_rpy: bool, remove_bin: bool) {
+fn prepare_ddlc_dir(transaction: &mut Transaction, path: &Path, remove_rpy: bool, remove_bin: bool) {
     if !path.is_dir() {
         return;
     }
@@ -207,7 +509,7 @@ fn prepare_ddlc_dir(path: &Path, remove_rpy: bool, remove_bin: bool) {
                     let ext = ext.unwrap();
                     match ext {
                         "rpy" | "rpyc" => {
-                            if remove_file(&item_path).is_err() {
+                            if transaction.remove_file(&item_path).is_err() {
                                 eprintln!("Failed to delete '{}'", item_path.display());
                             }
                         },
@@ -226,7 +528,7 @@ fn prepare_ddlc_dir(path: &Path, remove_rpy: bool, remove_bin: bool) {
                     let dir_name = dir_name.unwrap();
                     match dir_name {
                         "renpy" | "lib" => {
-                            if remove_dir_all(&item_path).is_err() {
+                            if transaction.remove_dir_all(&item_path).is_err() {
                                 eprintln!("Failed to delete '{}'", item_path.display());
                             }
                         }
@@ -249,17 +551,136 @@ fn get_content_size(client: &reqwest::Client, download_link: &str) -> Result<Con
     return Ok(content_size);
 }
 
+/// Writer that hashes the data it passes to the inner writer
+struct HashingWriter<'a, W: Write> {
+    inner: &'a mut W,
+    hasher: Option<&'a mut Sha256>
+}
+
+impl<'a, W: Write> Write for HashingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        if let Some(ref mut hasher) = self.hasher {
+            hasher.update(&buf[..written]);
+        }
+        return Ok(written);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+/// The download progress we show to the user
+#[derive(Clone, Copy)]
+pub struct DownloadStats {
+    pub downloaded: ContentSize,
+    pub total: ContentSize,
+    // Bytes per second, smoothed
+    pub rate: f64,
+    // Seconds left, None if we can't tell yet
+    pub eta: Option<u64>
+}
+
+/// Tracks the download rate, smooths it with an exponential moving average
+/// so the ETA doesn't jump around with every chunk
+struct DownloadTracker {
+    total: ContentSize,
+    last_downloaded: ContentSize,
+    last_update: Instant,
+    rate: Option<f64>
+}
+
+impl DownloadTracker {
+    /// Creates a new tracker, the downloaded bytes are the ones from the previous run
+    pub fn new(downloaded: ContentSize, total: ContentSize) -> Self {
+        return Self {
+            total,
+            last_downloaded: downloaded,
+            last_update: Instant::now(),
+            rate: None
+        };
+    }
+
+    /// Returns the stats for the given number of downloaded bytes
+    pub fn get_stats(&self, downloaded: ContentSize) -> DownloadStats {
+        let rate = self.rate.unwrap_or(0.0);
+        let eta = match rate > 0.0 {
+            true => Some((self.total.saturating_sub(downloaded) as f64 / rate).ceil() as u64),
+            false => None
+        };
+        return DownloadStats {
+            downloaded,
+            total: self.total,
+            rate,
+            eta
+        };
+    }
+
+    /// Updates the rate, returns the stats if it's time to report them
+    pub fn update(&mut self, downloaded: ContentSize) -> Option<DownloadStats> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_update);
+        if elapsed < DOWNLOAD_STATS_INTERVAL {
+            return None;
+        }
+
+        // The download might start over if the server can't resume
+        let sample = downloaded.saturating_sub(self.last_downloaded) as f64 / elapsed.as_secs_f64();
+        self.rate = Some(
+            match self.rate {
+                Some(rate) => DOWNLOAD_RATE_SMOOTHING*sample + (1.0-DOWNLOAD_RATE_SMOOTHING)*rate,
+                None => sample
+            }
+        );
+        self.last_downloaded = downloaded;
+        self.last_update = now;
+
+        return Some(self.get_stats(downloaded));
+    }
+}
+
+/// Moves to the end of the file, so the download continues from where the previous one stopped
+/// The hash must include the data from the previous run, so it's hashed first
+/// Returns the number of bytes we already have
+fn resume_download(file: &mut File, hasher: Option<&mut Sha256>) -> Result<ContentSize, io::Error> {
+    let downloaded = file.seek(SeekFrom::End(0))?;
+    if let Some(hasher) = hasher {
+        if downloaded != 0 {
+            file.seek(SeekFrom::Start(0))?;
+            io::copy(file, hasher)?;
+        }
+    }
+    return Ok(downloaded);
+}
+
+/// Compares the hash of the downloaded data with the expected one
+/// Truncates the file on mismatch so the next run doesn't resume a corrupted download
+fn verify_sha256(file: &mut File, hasher: Option<Sha256>, expected_sha256: Option<&str>) -> Result<(), DownloadError> {
+    if let (Some(hasher), Some(expected)) = (hasher, expected_sha256) {
+        let actual = format!("{:x}", hasher.finalize());
+        if !actual.eq_ignore_ascii_case(expected) {
+            file.set_len(0)?;
+            return Err(DownloadError::ChecksumMismatch(expected.to_string(), actual));
+        }
+    }
+    return Ok(());
+}
+
 /// Downloads data from the given link using the provided client
 /// the data is being written into the given file handler
-fn download_to_file(
+/// If the expected SHA-256 is given, the data is verified after downloading
+fn download_to_file<S: MessageSender>(
     client: &reqwest::Client,
-    sender: Sender<Message>,
+    sender: &S,
     app_state: &ThreadSafeState,
     download_link: &str,
     content_size: Option<ContentSize>,
+    expected_sha256: Option<&str>,
     file: &mut File
 ) -> Result<(), DownloadError> {
     const DEF_CHUNK_SIZE: ContentSize = 1024*1024*8 + 1;
+    const BUF_SIZE: usize = 64*1024;
 
     sender.send(Message::UpdateProgressBar(0.0));
 
@@ -272,10 +693,32 @@ fn download_to_file(
         Some(v) => v
     };
 
+    // The user should know the download can't be verified
+    if expected_sha256.is_none() {
+        sender.send(Message::ChecksumMissing);
+    }
+
+    // We only need to hash if we have something to compare with
+    let mut hasher = expected_sha256.map(|_| Sha256::new());
+
+    // Continue from where the previous download stopped
+    let mut total_downloaded: ContentSize = resume_download(file, hasher.as_mut())?;
+    if total_downloaded >= content_size {
+        sender.send(Message::UpdateProgressBar(1.0));
+        return verify_sha256(file, hasher, expected_sha256);
+    }
+    if content_size != 0 {
+        let pb_val = total_downloaded as f64 / content_size as f64;
+        sender.send(Message::UpdateProgressBar(pb_val));
+    }
+
+    let mut tracker = DownloadTracker::new(total_downloaded, content_size);
+    sender.send(Message::UpdateDownloadStats(tracker.get_stats(total_downloaded)));
+    let mut buf = vec![0; BUF_SIZE];
+
     let chunk_size: ContentSize = min(DEF_CHUNK_SIZE, content_size);
-    let mut low_bound: ContentSize = 0;
-    let mut up_bound: ContentSize = chunk_size;
-    let mut total_downloaded: ContentSize = 0;
+    let mut low_bound: ContentSize = total_downloaded;
+    let mut up_bound: ContentSize = min(low_bound+chunk_size, content_size+1);
 
     // println!("Content size: {}", content_size);
     loop {
@@ -290,8 +733,42 @@ fn download_to_file(
             return Err(DownloadError::InvalidStatusCode(status_code));
         }
 
-        // Write the received data
-        let received_chunk = resp.copy_to(file)? as ContentSize;
+        // The server ignored the range and is sending the whole file,
+        // we can't resume, so start the file over
+        if status_code == StatusCode::OK && low_bound != 0 {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            total_downloaded = 0;
+            if hasher.is_some() {
+                hasher = Some(Sha256::new());
+            }
+        }
+
+        // Write the received data, a chunk can take a while on slow links,
+        // so we report the progress as the data comes
+        let mut writer = HashingWriter { inner: file, hasher: hasher.as_mut() };
+        let mut received_chunk: ContentSize = 0;
+        loop {
+            let read = match resp.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into())
+            };
+            writer.write_all(&buf[..read])?;
+            received_chunk += read as ContentSize;
+
+            if let Some(stats) = tracker.update(total_downloaded+received_chunk) {
+                if content_size != 0 {
+                    let pb_val = stats.downloaded as f64 / content_size as f64;
+                    sender.send(Message::UpdateProgressBar(pb_val));
+                }
+                sender.send(Message::UpdateDownloadStats(stats));
+                if app_state.lock().unwrap().get_abort_flag() {
+                    return Ok(());
+                }
+            }
+        }
         total_downloaded += received_chunk;
 
         // Update progress bar
@@ -321,140 +798,1034 @@ fn download_to_file(
 
     // println!("Total downloaded: {}", total_downloaded);
 
-    return Ok(());
+    return verify_sha256(file, hasher, expected_sha256);
+}
+
+
+/// Calculates CRC32 of the given file
+fn get_file_crc32(path: &Path) -> Result<u32, io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Crc32Hasher::new();
+    let mut buf = vec![0; 64*1024];
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    return Ok(hasher.finalize());
 }
 
+/// Checks if the file on the disk is identical to the zip entry,
+/// compares the sizes first, so we only read files that might be the same
+pub fn is_same_file(path: &Path, size: ContentSize, crc32: u32) -> bool {
+    return match path.metadata() {
+        Ok(metadata) if metadata.is_file() && metadata.len() == size => {
+            get_file_crc32(path).map(|v| v == crc32).unwrap_or(false)
+        },
+        _ => false
+    };
+}
 
 /// Extracts a zip archive
-fn extract_archive(
-    sender: Sender<Message>,
+/// Existing files are backed up via the transaction before being overwritten,
+/// files identical to the entries are skipped
+/// If the filter is given, only the entries from it are extracted
+/// Returns the extracted files
+pub fn extract_archive<S: MessageSender>(
+    sender: &S,
     app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
     archive: &File,
-    destination: &Path
-) -> Result<(), ExtractionError> {
+    destination: &Path,
+    filter: Option<&HashSet<String>>
+) -> Result<Vec<ManifestEntry>, ExtractionError> {
+    return extract_archive_with(
+        sender,
+        app_state,
+        transaction,
+        archive,
+        destination,
+        |name, path| {
+            return match filter.map_or(true, |filter| filter.contains(name)) {
+                true => Some(path.to_path_buf()),
+                false => None
+            };
+        }
+    );
+}
+
+/// Returns the name of a manifest entry for the given relative path
+fn get_entry_name(path: &Path) -> String {
+    let parts: Vec<String> = path.components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    return parts.join("/");
+}
+
+/// Checks that the archive is within the extraction limits, so a malicious archive
+/// can't fill the disk, the sizes are taken from the central directory
+/// Returns the total uncompressed size
+fn check_extraction_limits(archive: &mut ZipArchive<&File>) -> Result<ContentSize, ExtractionError> {
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        return Err(
+            ExtractionError::LimitExceeded(
+                format!("{} entries, the limit is {}", archive.len(), MAX_ARCHIVE_ENTRIES)
+            )
+        );
+    }
+
+    let mut total_size: ContentSize = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        let size = file.size();
+
+        let ratio = size / file.compressed_size().max(1);
+        if size >= MIN_ENTRY_SIZE_FOR_RATIO && ratio > MAX_ENTRY_COMPRESSION_RATIO {
+            return Err(
+                ExtractionError::LimitExceeded(
+                    format!(
+                        "'{}' has compression ratio {}, the limit is {}",
+                        file.name(),
+                        ratio,
+                        MAX_ENTRY_COMPRESSION_RATIO
+                    )
+                )
+            );
+        }
+
+        total_size = total_size.saturating_add(size);
+        if total_size > MAX_ARCHIVE_UNCOMPRESSED_SIZE {
+            return Err(
+                ExtractionError::LimitExceeded(
+                    format!(
+                        "more than {} uncompressed, the limit is {}",
+                        utils::format_size(total_size),
+                        utils::format_size(MAX_ARCHIVE_UNCOMPRESSED_SIZE)
+                    )
+                )
+            );
+        }
+    }
+    return Ok(total_size);
+}
+
+/// Tests the archive without extracting it: the central directory must be readable
+/// and the data of every entry must match its CRC
+/// Nothing is written, so this is safe to run before we touch the game dir
+/// Returns the total uncompressed size
+fn test_archive<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    archive: &File
+) -> Result<ContentSize, ExtractionError> {
     sender.send(Message::UpdateProgressBar(0.0));
 
     if app_state.lock().unwrap().get_abort_flag() {
-        return Ok(());
+        return Ok(0);
     }
 
     let mut archive = ZipArchive::new(archive)?;
-    let total_files = archive.len();
+    let total_size = check_extraction_limits(&mut archive)?;
 
-    for i in 0..total_files {
+    let mut total_processed: ContentSize = 0;
+    for i in 0..archive.len() {
         let mut file = archive.by_index(i)?;
-
-        let file_path = file.enclosed_name()
-            .ok_or(ExtractionError::UnsafeFilepath(file.name().to_string()))?;
-
-        let extraction_path = destination.join(file_path);
-
-        // Extract the dir
         if file.is_dir() {
-            create_dir_all(&extraction_path)?;
+            continue;
         }
-        // Extract the file
-        else {
-            // Create the parent dir if needed
-            if let Some(parent_dir) = extraction_path.parent() {
-                if !parent_dir.exists() {
-                    create_dir_all(parent_dir)?;
+
+        // The CRC is checked once we read the entry to the end
+        let name = file.name().to_string();
+        let size = file.size();
+        let rv = copy_entry(
+            &mut file,
+            size,
+            &mut io::sink(),
+            |read| {
+                total_processed += read;
+                if total_size != 0 {
+                    sender.send(Message::UpdateProgressBar(total_processed as f64 / total_size as f64));
                 }
             }
-            // Create the file and write to it
-            let mut outfile = File::create(&extraction_path)?;
-            io::copy(&mut file, &mut outfile)?;
+        );
+        // The sink can't fail, so any I/O error comes from the archive data
+        match rv {
+            Err(ExtractionError::IOError(e)) => return Err(ExtractionError::CorruptedEntry(name, e)),
+            rv => rv?
+        };
+
+        if app_state.lock().unwrap().get_abort_flag() {
+            return Ok(total_size);
         }
+    }
 
-        // Update progres bar
-        let pb_val = (i as f64 + 1.0) / total_files as f64;
-        sender.send(Message::UpdateProgressBar(pb_val));
+    sender.send(Message::UpdateProgressBar(1.0));
+    return Ok(total_size);
+}
 
-        // See if we want to abort
+/// Tests the archives before the installation changes anything (see test_archive)
+/// Returns the total uncompressed size of the archives
+fn test_archives<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    archives: &[&File]
+) -> Result<ContentSize, ExtractionError> {
+    sender.send(Message::TestingArchives);
+    let mut total_size: ContentSize = 0;
+    for archive in archives {
+        total_size = total_size.saturating_add(test_archive(sender, app_state, archive)?);
         if app_state.lock().unwrap().get_abort_flag() {
-            return Ok(());
+            return Ok(total_size);
         }
+        sleep();
     }
-    return Ok(());
+    return Ok(total_size);
 }
 
+/// Copies the data of an entry, fails if there's more data than the entry declares,
+/// the declared sizes are what we check the limits against
+/// on_progress is called with the number of bytes after each chunk
+fn copy_entry<R, W, F>(
+    entry: &mut R,
+    size: ContentSize,
+    writer: &mut W,
+    mut on_progress: F
+) -> Result<(), ExtractionError>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(ContentSize)
+{
+    const CHUNK_SIZE: usize = 1024*1024;
+
+    // One extra byte to tell if there's more
+    let mut entry = entry.take(size + 1);
+    let mut buf = vec![0; CHUNK_SIZE];
+    let mut copied: ContentSize = 0;
+    loop {
+        let read = match entry.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into())
+        };
+        copied += read as ContentSize;
+        if copied > size {
+            return Err(
+                ExtractionError::LimitExceeded(format!("an entry has more data than the declared {size} bytes"))
+            );
+        }
+        writer.write_all(&buf[..read])?;
+        on_progress(read as ContentSize);
+    }
+    return Ok(());
+}
 
-/// Creates a temp dir for the installer temp data
-fn create_temp_dir() -> Result<tempfile::TempDir, io::Error> {
-    return tempfile::Builder::new()
-        .prefix(".mas_installer-")
-        .tempdir();
+/// Returns the space we lack on the filesystem of the given path, None if there's enough
+/// If we can't tell the available space, we assume there's enough
+fn get_missing_space(path: &Path, required: u64) -> Option<u64> {
+    return match utils::get_available_space(path) {
+        Ok(available) if available < required => Some(available),
+        Ok(_) => None,
+        Err(e) => {
+            eprintln!("Failed to get available disk space for '{}': {e}", path.display());
+            None
+        }
+    };
 }
 
-/// Creates a temp file for the installer data
-fn create_temp_file(temp_dir: &tempfile::TempDir, name: &str) -> Result<File, io::Error> {
-    let fp = temp_dir.path().join(name);
-    return File::options()
-        .write(true)
-        .read(true)
-        .create(true)
-        .truncate(true)
-        .open(&fp);
+/// Checks that the cache has enough space for the assets we're going to download,
+/// the parts we've already downloaded don't count
+fn check_download_space(version: &str, assets: &[&GHAsset]) -> Result<(), DownloadError> {
+    let required: ContentSize = assets.iter()
+        .map(|asset| asset.size - cache::get_downloaded_size(version, &asset.name, asset.size))
+        .sum();
+    let cache_dir = cache::get_releases_dir();
+    if let Some(available) = get_missing_space(&cache_dir, required) {
+        return Err(DownloadError::NotEnoughSpace(cache_dir, required, available));
+    }
+    return Ok(());
 }
 
+/// Checks that the destination has enough space for the whole installation before we change anything:
+/// all the archives uncompressed and the copies of the vanilla files the transaction keeps
+/// The replaced files stay in the staging dir until the commit, so they don't free any space
+fn check_install_space(
+    transaction: &Transaction,
+    destination: &Path,
+    mas_file: &File,
+    archives_size: ContentSize
+) -> Result<(), ExtractionError> {
+    let mut archive = ZipArchive::new(mas_file)?;
+    let mut targets = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        if let Some(file_path) = file.enclosed_name() {
+            targets.push(destination.join(file_path));
+        }
+    }
 
-/// This runs cleanup logic on SUCCESSFUL download
-fn cleanup(sender: Sender<Message>, mas_temp_file: File, spr_temp_file: File) {
-    sender.send(Message::CleaningUp);
-    sender.send(Message::UpdateProgressBar(0.0));
-    drop(mas_temp_file);
-    drop(spr_temp_file);
-    sleep();
-    sender.send(Message::UpdateProgressBar(1.0));
-    sleep();
-    sender.send(Message::Done);
+    let required = archives_size.saturating_add(transaction.get_originals_size(targets.iter()));
+    if let Some(available) = get_missing_space(destination, required) {
+        return Err(ExtractionError::NotEnoughSpace(destination.to_path_buf(), required, available));
+    }
+    return Ok(());
 }
 
+/// Checks that the destination has enough space for the entries we're going to extract,
+/// the sizes are taken from the central directory
+/// Entries with a file of the same size at the target are likely unchanged and don't count
+/// Returns the total size of the entries, unchanged or not
+fn check_extraction_space<F>(
+    archive: &mut ZipArchive<&File>,
+    destination: &Path,
+    get_target: &F
+) -> Result<ContentSize, ExtractionError>
+where
+    F: Fn(&str, &Path) -> Option<PathBuf>
+{
+    let mut total_size: ContentSize = 0;
+    let mut required: ContentSize = 0;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let file_path = file.enclosed_name()
+            .ok_or(ExtractionError::UnsafeFilepath(file.name().to_string()))?;
+        let target = match get_target(file.name(), file_path) {
+            Some(target) => destination.join(target),
+            None => continue
+        };
+        total_size += file.size();
+        if target.metadata().map_or(false, |m| m.len() == file.size()) {
+            continue;
+        }
+        required += file.size();
+    }
+
+    if let Some(available) = get_missing_space(destination, required) {
+        return Err(ExtractionError::NotEnoughSpace(destination.to_path_buf(), required, available));
+    }
+    return Ok(total_size);
+}
 
-/// Main method to handle game installation process, downloads it into a temp folder and then extracts
-pub fn install_game(
-    sender: Sender<Message>,
-    app_state: &ThreadSafeState
-) -> InstallResult {
-    sender.send(Message::Preparing);
+/// Extracts a zip archive placing the entries where get_target says,
+/// it gets the name and the path of an entry and returns the path relative
+/// to the destination, None to skip the entry
+/// Returns the extracted files, relative to the destination
+fn extract_archive_with<S, F>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    archive: &File,
+    destination: &Path,
+    get_target: F
+) -> Result<Vec<ManifestEntry>, ExtractionError>
+where
+    S: MessageSender,
+    F: Fn(&str, &Path) -> Option<PathBuf>
+{
     sender.send(Message::UpdateProgressBar(0.0));
 
+    let mut entries = Vec::new();
+
     if app_state.lock().unwrap().get_abort_flag() {
-        return Ok(());
+        return Ok(entries);
     }
 
-    let client = build_client()?;
+    let mut archive = ZipArchive::new(archive)?;
+    check_extraction_limits(&mut archive)?;
+    let total_size = check_extraction_space(&mut archive, destination, &get_target)?;
 
-    // Get download link
-    let data = get_release_data(&client)?;
-    let main_asset = match app_state.lock().unwrap().get_deluxe_ver_flag() {
-        true => data.dlx_ver_asset,
-        false => data.def_ver_asset
+    let total_files = archive.len();
+    let mut total_written: usize = 0;
+    let mut total_skipped: usize = 0;
+    // The progress is weighted by the size, so many small files don't outrun a big one
+    let mut total_processed: ContentSize = 0;
+    let update_progress = |processed: ContentSize| {
+        if total_size != 0 {
+            sender.send(Message::UpdateProgressBar(processed as f64 / total_size as f64));
+        }
+    };
+
+    for i in 0..total_files {
+        let mut file = archive.by_index(i)?;
+
+        let file_path = file.enclosed_name()
+            .ok_or(ExtractionError::UnsafeFilepath(file.name().to_string()))?;
+
+        // Not the entry we want
+        let target = match get_target(file.name(), file_path) {
+            Some(target) => target,
+            None => continue
+        };
+        let extraction_path = destination.join(&target);
+
+        if file.is_file() {
+            app_state.lock().unwrap().set_current_entry(Some(get_entry_name(&target)));
+            sender.send(Message::ExtractingEntry);
+        }
+
+        // Extract the dir
+        if file.is_dir() {
+            transaction.create_dir_all(&extraction_path)?;
+        }
+        // Skip the file if it didn't change (e.g. an upgrade)
+        else if is_same_file(&extraction_path, file.size(), file.crc32()) {
+            total_skipped += 1;
+        }
+        // Extract the file
+        else {
+            // Create the parent dir if needed
+            if let Some(parent_dir) = extraction_path.parent() {
+                if !parent_dir.exists() {
+                    transaction.create_dir_all(parent_dir)?;
+                }
+            }
+            // Create the file and write to it
+            transaction.prepare_write(&extraction_path)?;
+            let size = file.size();
+            let mut outfile = File::create(&extraction_path)?;
+            let mut written: ContentSize = 0;
+            copy_entry(
+                &mut file,
+                size,
+                &mut outfile,
+                |chunk| {
+                    written += chunk;
+                    update_progress(total_processed + written);
+                }
+            )?;
+            total_written += 1;
+        }
+        // Only what we wrote, the rollback can't restore the permissions of the rest
+        if transaction.is_new_or_replaced(&extraction_path) {
+            set_permissions(&extraction_path, file.unix_mode(), file.is_dir())?;
+        }
+
+        if file.is_file() {
+            entries.push(
+                ManifestEntry {
+                    path: get_entry_name(&target),
+                    size: file.size(),
+                    crc32: file.crc32()
+                }
+            );
+            // Update progres bar
+            total_processed += file.size();
+            update_progress(total_processed);
+        }
+
+        // See if we want to abort
+        if app_state.lock().unwrap().get_abort_flag() {
+            return Ok(entries);
+        }
+    }
+
+    app_state.lock().unwrap().set_current_entry(None);
+    sender.send(Message::UpdateProgressBar(1.0));
+    sender.send(Message::Extracted(total_written, total_skipped));
+
+    return Ok(entries);
+}
+
+
+/// Returns the files in the given zip archive
+pub fn get_archive_entries(archive: &File) -> Result<Vec<ManifestEntry>, ExtractionError> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut entries = Vec::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.enclosed_name().is_none() {
+            return Err(ExtractionError::UnsafeFilepath(file.name().to_string()));
+        }
+        if file.is_dir() {
+            continue;
+        }
+        entries.push(
+            ManifestEntry {
+                path: file.name().to_string(),
+                size: file.size(),
+                crc32: file.crc32()
+            }
+        );
+    }
+    return Ok(entries);
+}
+
+
+/// Applies the permissions stored in the archive to the extracted file or dir
+/// The owner always keeps full access, so we can update the files later
+#[cfg(unix)]
+fn set_permissions(path: &Path, mode: Option<u32>, is_dir: bool) -> Result<(), io::Error> {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    let mode = match mode {
+        Some(mode) => mode & 0o777,
+        None => return Ok(())
+    };
+    let mode = match is_dir {
+        true => mode | 0o700,
+        false => mode | 0o600
     };
+    return std::fs::set_permissions(path, Permissions::from_mode(mode));
+}
+
+/// Applies the permissions stored in the archive to the extracted file or dir
+/// Only unix permissions are supported
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _mode: Option<u32>, _is_dir: bool) -> Result<(), io::Error> {
+    return Ok(());
+}
+
+/// Returns the files that must be executable to launch the game:
+/// the launch script, the binaries Ren'Py bundles (files without extension in lib/<platform>),
+/// and the app binary on macOS
+fn get_game_executables(app_state: &ThreadSafeState) -> Vec<PathBuf> {
+    let destination = get_destination(app_state);
+    let mut executables = vec![destination.join("DDLC.sh")];
+
+    let mut bin_dirs = Vec::new();
+    if let Ok(content) = read_dir(destination.join("lib")) {
+        for item in content.flatten() {
+            let name = item.file_name().to_string_lossy().into_owned();
+            if name.starts_with("linux-") || name.starts_with("darwin-") {
+                bin_dirs.push(item.path());
+            }
+        }
+    }
+    if env::consts::OS == "macos" {
+        bin_dirs.push(app_state.lock().unwrap().get_extraction_dir().join("Contents/MacOS"));
+    }
+
+    for bin_dir in bin_dirs {
+        if let Ok(content) = read_dir(bin_dir) {
+            executables.extend(
+                content.flatten()
+                    .map(|item| item.path())
+                    // Shared libraries have extensions, e.g. libpython2.7.so.1.0
+                    .filter(|path| path.is_file() && path.extension().is_none())
+            );
+        }
+    }
+
+    return executables.into_iter().filter(|path| path.is_file()).collect();
+}
+
+/// Makes sure the game can be launched, archives made on Windows
+/// and older installations might've lost the executable bits
+#[cfg(unix)]
+fn fix_game_executables(app_state: &ThreadSafeState) {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt};
+
+    for path in get_game_executables(app_state) {
+        let mode = match path.metadata() {
+            Ok(metadata) => metadata.permissions().mode(),
+            Err(_) => continue
+        };
+        // Executable for everyone who can read it
+        let new_mode = mode | (mode & 0o444) >> 2 | 0o100;
+        if new_mode == mode {
+            continue;
+        }
+        println!("Making '{}' executable", path.display());
+        if let Err(e) = std::fs::set_permissions(&path, Permissions::from_mode(new_mode)) {
+            eprintln!("Failed to make '{}' executable: {e}", path.display());
+        }
+    }
+}
+
+/// Makes sure the game can be launched
+/// Only unix permissions are supported
+#[cfg(not(unix))]
+fn fix_game_executables(_app_state: &ThreadSafeState) {}
+
+/// Returns the spritepacks in the given archive (the top-level dirs), sorted
+pub fn get_archive_spritepacks(archive: &File) -> Result<Vec<String>, ExtractionError> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut spritepacks = BTreeSet::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        // Files in the root aren't spritepacks
+        if let Some((spritepack, _)) = file.name().split_once('/') {
+            spritepacks.insert(spritepack.to_string());
+        }
+    }
+    return Ok(spritepacks.into_iter().collect());
+}
+
+/// Returns the entries of the given spritepacks in the archive
+fn get_spritepack_entries(archive: &File, spritepacks: &HashSet<String>) -> Result<HashSet<String>, ExtractionError> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut entries = HashSet::new();
+
+    for i in 0..archive.len() {
+        let name = archive.by_index_raw(i)?.name().to_string();
+        let is_selected = name.split_once('/')
+            .map_or(false, |(spritepack, _)| spritepacks.contains(spritepack));
+        if is_selected {
+            entries.insert(name);
+        }
+    }
+    return Ok(entries);
+}
+
+/// Waits until the GUI writes the answer of the user into the app state, None if aborted
+fn wait_for_answer<T, F>(app_state: &ThreadSafeState, take_answer: F) -> Option<T>
+where
+    F: Fn(&mut AppState) -> Option<T>
+{
+    loop {
+        let mut app_state = app_state.lock().unwrap();
+        if app_state.get_abort_flag() {
+            return None;
+        }
+        if let Some(answer) = take_answer(&mut app_state) {
+            return Some(answer);
+        }
+        drop(app_state);
+        thread::sleep(PAUSE_DURATION);
+    }
+}
+
+/// Returns the spritepacks the user wants to install, None if aborted
+/// The GUI asks the user and we wait for the answer, otherwise the selection
+/// from the app state is used, all the spritepacks if there's none
+fn select_spritepacks<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    spritepacks: Vec<String>
+) -> Option<Vec<String>> {
+    if !sender.is_interactive() {
+        let selected = app_state.lock().unwrap().get_selected_spritepacks().cloned();
+        return match selected {
+            Some(selected) => {
+                for spritepack in selected.iter().filter(|s| !spritepacks.contains(s)) {
+                    eprintln!("Spritepack '{spritepack}' isn't in the archive, skipping");
+                }
+                Some(selected)
+            },
+            None => Some(spritepacks)
+        };
+    }
+
+    {
+        let mut app_state = app_state.lock().unwrap();
+        app_state.set_spritepacks(spritepacks);
+        app_state.set_selected_spritepacks(None);
+    }
+    sender.send(Message::SelectSpritepacks);
+
+    return wait_for_answer(app_state, AppState::take_selected_spritepacks);
+}
+
+/// Returns the path of a spritepack entry inside the MAS dir, None if it's not a game file
+/// Each pack has its own layout (e.g. <pack>/<sprite>/game/mod_assets/...),
+/// everything after the game dir of the pack goes into the game dir of MAS
+fn get_spritepack_game_path(path: &Path) -> Option<PathBuf> {
+    let mut components = path.components();
+    // Skip the pack
+    components.next()?;
+    while let Some(component) = components.next() {
+        if component.as_os_str() == "game" {
+            return Some(Path::new("game").join(components.as_path()));
+        }
+    }
+    return None;
+}
+
+/// Returns the spritepack an entry belongs to
+fn get_entry_spritepack(path: &Path) -> Option<&str> {
+    return path.components().next()?.as_os_str().to_str();
+}
+
+/// Returns the files of the given spritepacks that would replace different files
+/// in the MAS dir or the files of other spritepacks
+fn get_spritepack_conflicts(
+    archive: &File,
+    spritepacks: &HashSet<String>,
+    mas_dir: &Path
+) -> Result<Vec<String>, ExtractionError> {
+    let mut archive = ZipArchive::new(archive)?;
+    let mut targets = HashSet::new();
+    let mut conflicts = BTreeSet::new();
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let file_path = file.enclosed_name()
+            .ok_or(ExtractionError::UnsafeFilepath(file.name().to_string()))?;
+        if !get_entry_spritepack(file_path).map_or(false, |s| spritepacks.contains(s)) {
+            continue;
+        }
+        let target = match get_spritepack_game_path(file_path) {
+            Some(target) => target,
+            None => continue
+        };
+
+        let target_path = mas_dir.join(&target);
+        let is_conflict = !targets.insert(target.clone())
+            || (target_path.is_file() && !is_same_file(&target_path, file.size(), file.crc32()));
+        if is_conflict {
+            conflicts.insert(get_entry_name(&target));
+        }
+    }
+    return Ok(conflicts.into_iter().collect());
+}
+
+/// Returns true if the spritepacks should replace the conflicting files, None if aborted
+/// The GUI asks the user and we wait for the answer, otherwise the flag
+/// from the app state is used, the files are kept if there's none
+fn resolve_spritepack_conflicts<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    conflicts: Vec<String>
+) -> Option<bool> {
+    for conflict in conflicts.iter() {
+        eprintln!("Spritepack file conflicts with an existing one: {conflict}");
+    }
+
+    if !sender.is_interactive() {
+        return Some(app_state.lock().unwrap().get_overwrite_spr_flag().unwrap_or(false));
+    }
+
+    {
+        let mut app_state = app_state.lock().unwrap();
+        app_state.set_spritepack_conflicts(conflicts);
+        app_state.set_overwrite_spr_flag(None);
+    }
+    sender.send(Message::SpritepackConflicts);
+
+    return wait_for_answer(app_state, AppState::take_overwrite_spr_flag);
+}
+
+/// Extracts the given spritepacks into the game dir of MAS
+/// Returns the extracted files, relative to the MAS dir
+fn extract_spritepacks_into_game<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    spr_file: &File,
+    spritepacks: &HashSet<String>
+) -> Result<Vec<ManifestEntry>, InstallError> {
+    let mas_dir = get_destination(app_state);
+
+    let conflicts = get_spritepack_conflicts(spr_file, spritepacks, &mas_dir)?;
+    let mut skipped = HashSet::new();
+    if !conflicts.is_empty() {
+        match resolve_spritepack_conflicts(sender, app_state, conflicts.clone()) {
+            Some(true) => println!("Replacing {} conflicting file(s)...", conflicts.len()),
+            Some(false) => {
+                println!("Skipping {} conflicting file(s)...", conflicts.len());
+                skipped.extend(conflicts);
+            },
+            None => return Ok(Vec::new())
+        };
+    }
+
+    sender.send(Message::ExtractingSpr);
+    let entries = extract_archive_with(
+        sender,
+        app_state,
+        transaction,
+        spr_file,
+        &mas_dir,
+        |_, path| {
+            if !get_entry_spritepack(path).map_or(false, |s| spritepacks.contains(s)) {
+                return None;
+            }
+            let target = get_spritepack_game_path(path)?;
+            return match skipped.contains(&get_entry_name(&target)) {
+                true => None,
+                false => Some(target)
+            };
+        }
+    )?;
+    return Ok(entries);
+}
+
+/// Extracts the spritepacks the user wants from the spritepacks archive,
+/// either into the spritepacks dir or into the game dir
+/// Returns the extracted files
+fn extract_spritepacks<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    spr_file: &File
+) -> Result<Vec<ManifestEntry>, InstallError> {
+    let spritepacks = get_archive_spritepacks(spr_file)?;
+    let selected: HashSet<String> = match select_spritepacks(sender, app_state, spritepacks) {
+        Some(selected) => selected.into_iter().collect(),
+        None => return Ok(Vec::new())
+    };
+    if selected.is_empty() {
+        println!("No spritepacks selected, skipping");
+        return Ok(Vec::new());
+    }
+
+    if app_state.lock().unwrap().get_spr_into_game_flag() {
+        return extract_spritepacks_into_game(sender, app_state, transaction, spr_file, &selected);
+    }
+
+    // The filter needs the entries, not the spritepacks
+    let filter = get_spritepack_entries(spr_file, &selected)?;
+
+    sender.send(Message::ExtractingSpr);
+    let entries = extract_archive(
+        sender,
+        app_state,
+        transaction,
+        spr_file,
+        &get_spr_destination(app_state),
+        Some(&filter)
+    )?;
+    return Ok(entries);
+}
+
+
+/// This runs cleanup logic on SUCCESSFUL download
+/// The transaction is committed here, before Done, after that nothing is restored
+fn cleanup<S: MessageSender>(sender: &S, app_state: &ThreadSafeState, transaction: &mut Transaction) {
+    sender.send(Message::CleaningUp);
+    sender.send(Message::UpdateProgressBar(0.0));
+    fix_game_executables(app_state);
+    transaction.commit();
+    sleep();
+    sender.send(Message::UpdateProgressBar(1.0));
+    sleep();
+    sender.send(Message::Done);
+}
+
+
+/// Returns the directory to extract MAS into
+pub fn get_destination(app_state: &ThreadSafeState) -> PathBuf {
     let mut destination = app_state.lock().unwrap().get_extraction_dir().clone();
     // Since mac is pain, we have to adjust the destination to be
     // within the app
     if env::consts::OS == "macos" {
         destination.push("Contents/Resources/autorun");
     }
+    return destination;
+}
 
-    sender.send(Message::UpdateProgressBar(0.5));
-    sleep();
+/// Returns the directory to extract spritepacks into
+pub fn get_spr_destination(app_state: &ThreadSafeState) -> PathBuf {
+    let mut destination = get_destination(app_state);
+    // We don't want to add spritepacks inside the app, so find the parent dir
+    // and extract there
+    if env::consts::OS == "macos" {
+        for _ in 0..4 {
+            destination.pop();
+        }
+    }
+    return destination.join("spritepacks");
+}
+
+
+/// Writes the manifest of the installation into the game dir
+/// The file goes thru the transaction, so it's removed if the installation doesn't finish
+pub fn write_manifest(
+    transaction: &mut Transaction,
+    destination: &Path,
+    manifest: &Manifest
+) -> Result<(), io::Error> {
+    let manifest_path = manifest::get_manifest_path(destination);
+    transaction.prepare_write(&manifest_path)?;
+    return manifest.save(&manifest_path);
+}
+
+
+/// Returns the staging dirs left in the given dir by the installations that didn't finish,
+/// restoring a backup stages the files in the backups dir, so it's checked too
+pub fn find_interrupted_installs(dir: &Path) -> Vec<PathBuf> {
+    let mut staging_dirs = transaction::find_leftover_staging_dirs(dir);
+    staging_dirs.extend(transaction::find_leftover_staging_dirs(&saves::get_backups_dir()));
+    return staging_dirs;
+}
+
+
+/// Returns the key of the asset the given archive is for (see ASSETS_NAMES_RE_MAP)
+/// None if the file name doesn't match any asset
+pub fn get_archive_kind(path: &Path) -> Option<&'static str> {
+    let file_name = path.file_name()?.to_str()?;
+    return crate::ASSETS_NAMES_RE_MAP.iter()
+        .find(|(_, re)| re.is_match(file_name))
+        .map(|(k, _)| *k);
+}
+
+/// Returns the version of the given MAS archive from its name, e.g. 0.12.15 for
+/// Monika_After_Story-0.12.15-Mod.zip, None if it's not a MAS archive
+pub fn get_archive_version(path: &Path) -> Option<String> {
+    if !matches!(get_archive_kind(path), Some("def_ver") | Some("dlx_ver")) {
+        return None;
+    }
+    let file_name = path.file_name()?.to_str()?;
+    return file_name.strip_prefix("Monika_After_Story-")?
+        .split('-')
+        .next()
+        .map(str::to_string);
+}
+
+/// Checks if the given archive is a MAS archive (default or deluxe)
+pub fn is_mas_archive(path: &Path) -> bool {
+    return path.is_file() && matches!(get_archive_kind(path), Some("def_ver") | Some("dlx_ver"));
+}
+
+/// Checks if the given archive is a spritepacks archive
+pub fn is_spr_archive(path: &Path) -> bool {
+    return path.is_file() && get_archive_kind(path) == Some("spr");
+}
 
-    // Create temp structures
-    let temp_dir = create_temp_dir()?;
-    let mut mas_temp_file = create_temp_file(&temp_dir, "mas.tmp")?;
-    let mut spr_temp_file = create_temp_file(&temp_dir, "spr.tmp")?;
+
+/// Installs the game from the archives on the disk, nothing is downloaded
+/// Spritepacks are installed if the user wants them or the archive is given
+fn install_game_offline<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    mas_archive: &Path,
+    spr_archive: Option<&Path>
+) -> InstallResult {
+    let install_spr = app_state.lock().unwrap().get_install_spr_flag() || spr_archive.is_some();
+    if install_spr && spr_archive.is_none() {
+        return Err(InstallError::MissingLocalArchive("spritepacks"));
+    }
+
+    let destination = get_destination(app_state);
+    let variant = match get_archive_kind(mas_archive) {
+        Some("dlx_ver") => Variant::Deluxe,
+        _ => Variant::Default
+    };
+    let version = get_archive_version(mas_archive);
+    if let Some(ref version) = version {
+        check_downgrade(app_state, version)?;
+    }
+
+    let mas_file = File::open(mas_archive)?;
+    let spr_file = match spr_archive {
+        Some(spr_archive) => Some(File::open(spr_archive)?),
+        None => None
+    };
+
+    // Don't touch the game dir until we know the archives are fine and fit
+    let mut archives = vec![&mas_file];
+    archives.extend(spr_file.as_ref());
+    let archives_size = test_archives(sender, app_state, &archives)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
+    check_install_space(transaction, &destination, &mas_file, archives_size)?;
 
     // Remove old rpy/rpyc
-    // Yeah...some people have rpy in the base dir...
-    prepare_ddlc_dir(&destination, true, false);
-    prepare_ddlc_dir(&destination.join("game"), true, false);
+    prepare_ddlc_dir(transaction, &destination, true, false);
+    prepare_ddlc_dir(transaction, &destination.join("game"), true, false);
 
-    sender.send(Message::UpdateProgressBar(1.0));
+    // Install MAS
+    sender.send(Message::Extracting);
+    let files = extract_archive(
+        sender,
+        app_state,
+        transaction,
+        &mas_file,
+        &destination,
+        None
+    )?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
     sleep();
 
-    // Install MAS
+    // Install spritepacks
+    let mut spritepack_files = Vec::new();
+    if let Some(spr_file) = spr_file {
+        spritepack_files = extract_spritepacks(sender, app_state, transaction, &spr_file)?;
+        if app_state.lock().unwrap().get_abort_flag() {
+            return Ok(());
+        }
+        sleep();
+    }
+
+    // The version from the name of the archive stands for the release
+    let spritepacks_in_game = app_state.lock().unwrap().get_spr_into_game_flag();
+    let manifest = Manifest::new(version, variant, files, spritepack_files, spritepacks_in_game);
+    write_manifest(transaction, &destination, &manifest)?;
+
+    cleanup(sender, app_state, transaction);
+
+    return Ok(());
+}
+
+
+/// Checks that we're not going to install an older release over a newer one,
+/// unless the user allowed it
+fn check_downgrade(app_state: &ThreadSafeState, requested_version: &str) -> InstallResult {
+    if app_state.lock().unwrap().get_allow_downgrade_flag() {
+        return Ok(());
+    }
+    if let Some(installed_version) = version::get_installed_version(&get_destination(app_state)) {
+        if version::compare_versions(&installed_version, requested_version) == Some(Ordering::Greater) {
+            return Err(InstallError::Downgrade(installed_version, requested_version.to_string()));
+        }
+    }
+    return Ok(());
+}
+
+
+/// Installs the game downloading the archives into the cache, the spritepacks archive
+/// isn't downloaded if the user has it
+fn install_game_online<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    local_spr_archive: Option<&Path>
+) -> InstallResult {
+    let client = build_client()?;
+
+    // Get download link
+    let (source, tag) = {
+        let app_state = app_state.lock().unwrap();
+        (app_state.get_release_source().clone(), app_state.get_release_tag().cloned())
+    };
+    let data = get_release_data(&client, &source, tag.as_deref())?;
+    check_downgrade(app_state, &data.version)?;
+    let variant = match app_state.lock().unwrap().get_deluxe_ver_flag() {
+        true => Variant::Deluxe,
+        false => Variant::Default
+    };
+    let main_asset = match variant {
+        Variant::Deluxe => &data.dlx_ver_asset,
+        Variant::Default => &data.def_ver_asset
+    };
+    let main_sha256 = data.get_expected_sha256(main_asset);
+    if main_sha256.is_none() {
+        eprintln!("No checksum published for '{}', skipping verification", main_asset.name);
+    }
+    let destination = get_destination(app_state);
+
+    // Make sure we can fit everything before downloading anything,
+    // the extraction is checked once we know the uncompressed sizes
+    let mut assets = vec![main_asset];
+    let install_spr = app_state.lock().unwrap().get_install_spr_flag();
+    if install_spr && local_spr_archive.is_none() {
+        assets.push(&data.spr_asset);
+    }
+    check_download_space(&data.version, &assets)?;
+
+    // Open the download file, it might have data from a previous run
+    // or be complete if we already downloaded this release
+    let mut mas_file = cache::open_asset_file(&data.version, &main_asset.name, main_asset.size)?;
+
+    // Download everything first, a network failure mustn't leave the game half-installed
     sender.send(Message::Downloading);
     download_to_file(
         &client,
@@ -462,69 +1833,257 @@ pub fn install_game(
         app_state,
         &main_asset.browser_download_url,
         Some(main_asset.size),
-        &mut mas_temp_file
+        main_sha256,
+        &mut mas_file
     )?;
     if app_state.lock().unwrap().get_abort_flag() {
         return Ok(());
     }
+    let mas_file = cache::complete_asset_file(mas_file, &data.version, &main_asset.name)?;
     sleep();
 
+    // No need to download spritepacks if the user has the archive
+    let spr_file = match local_spr_archive {
+        Some(spr_archive) => Some(File::open(spr_archive)?),
+        None if install_spr => {
+            let spr_sha256 = data.get_expected_sha256(&data.spr_asset);
+            if spr_sha256.is_none() {
+                eprintln!("No checksum published for '{}', skipping verification", data.spr_asset.name);
+            }
+            let mut spr_file = cache::open_asset_file(&data.version, &data.spr_asset.name, data.spr_asset.size)?;
+            sender.send(Message::DownloadingSpr);
+            download_to_file(
+                &client,
+                sender,
+                app_state,
+                &data.spr_asset.browser_download_url,
+                Some(data.spr_asset.size),
+                spr_sha256,
+                &mut spr_file
+            )?;
+            if app_state.lock().unwrap().get_abort_flag() {
+                return Ok(());
+            }
+            sleep();
+            Some(cache::complete_asset_file(spr_file, &data.version, &data.spr_asset.name)?)
+        },
+        None => None
+    };
+
+    // Don't touch the game dir until we know the archives are fine and fit
+    let mut archives = vec![&mas_file];
+    archives.extend(spr_file.as_ref());
+    let archives_size = test_archives(sender, app_state, &archives)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
+    check_install_space(transaction, &destination, &mas_file, archives_size)?;
+
+    // Remove old rpy/rpyc
+    // Yeah...some people have rpy in the base dir...
+    prepare_ddlc_dir(transaction, &destination, true, false);
+    prepare_ddlc_dir(transaction, &destination.join("game"), true, false);
+
+    // Install MAS
     sender.send(Message::Extracting);
-    extract_archive(
+    let files = extract_archive(
         sender,
         app_state,
-        &mas_temp_file,
-        &destination
+        transaction,
+        &mas_file,
+        &destination,
+        None
     )?;
     if app_state.lock().unwrap().get_abort_flag() {
         return Ok(());
     }
     sleep();
 
-    // Quit early if the user doesn't want spritepacks
-    if !app_state.lock().unwrap().get_install_spr_flag() {
-        cleanup(sender, mas_temp_file, spr_temp_file);
-        return Ok(());
-    }
-
-    // We don't want to add spritepacks inside the app, so find the parent dir
-    // and extract there
-    if env::consts::OS == "macos" {
-        for _ in 0..4 {
-            destination.pop();
+    // Install spritepacks
+    let mut spritepack_files = Vec::new();
+    if let Some(ref spr_file) = spr_file {
+        spritepack_files = extract_spritepacks(sender, app_state, transaction, spr_file)?;
+        if app_state.lock().unwrap().get_abort_flag() {
+            return Ok(());
         }
+        sleep();
     }
 
-    // Install spritepacks
-    sender.send(Message::DownloadingSpr);
+    let spritepacks_in_game = spr_file.is_some() && app_state.lock().unwrap().get_spr_into_game_flag();
+    drop(mas_file);
+    drop(spr_file);
+    let manifest = Manifest::new(Some(data.version.clone()), variant, files, spritepack_files, spritepacks_in_game);
+    write_manifest(transaction, &destination, &manifest)?;
+    cleanup(sender, app_state, transaction);
+
+    return Ok(());
+}
+
+/// Downloads the MAS archive of the given variant into the cache,
+/// uses the release from the app state
+/// Returns the archive and the tag of its release
+pub fn download_mas_archive<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    variant: Variant
+) -> Result<(File, String), InstallError> {
+    let client = build_client()?;
+
+    let (source, tag) = {
+        let app_state = app_state.lock().unwrap();
+        (app_state.get_release_source().clone(), app_state.get_release_tag().cloned())
+    };
+    let data = get_release_data(&client, &source, tag.as_deref())?;
+    let asset = match variant {
+        Variant::Deluxe => &data.dlx_ver_asset,
+        Variant::Default => &data.def_ver_asset
+    };
+
+    let mut file = cache::open_asset_file(&data.version, &asset.name, asset.size)?;
+    sender.send(Message::Downloading);
     download_to_file(
         &client,
         sender,
         app_state,
-        &data.spr_asset.browser_download_url,
-        Some(data.spr_asset.size),
-        &mut spr_temp_file
+        &asset.browser_download_url,
+        Some(asset.size),
+        data.get_expected_sha256(asset),
+        &mut file
     )?;
     if app_state.lock().unwrap().get_abort_flag() {
-        return Ok(());
+        return Ok((file, data.version));
     }
-    sleep();
+    let file = cache::complete_asset_file(file, &data.version, &asset.name)?;
+    return Ok((file, data.version));
+}
+
+
+/// Makes the transaction keep the vanilla files we're about to replace, so uninstall can restore them
+/// The files of the previous installation aren't vanilla, so they are skipped
+fn keep_vanilla_files(transaction: &mut Transaction, destination: &Path) {
+    let manifest_path = manifest::get_manifest_path(destination);
+    let mut exclude = HashSet::new();
+
+    match Manifest::load(&manifest_path) {
+        Ok(manifest) => {
+            exclude.extend(
+                manifest.get_mas_dir_files().map(|entry| destination.join(&entry.path))
+            );
+        },
+        Err(_) => {
+            // Installed without a manifest, we can't tell the mod files from the vanilla ones
+            if destination.join("game/definitions.rpyc").exists() {
+                eprintln!("Found an existing installation without a manifest, not keeping vanilla files");
+                return;
+            }
+        }
+    };
+    exclude.insert(manifest_path);
+
+    transaction.keep_originals(destination, &uninstaller::get_vanilla_backup_dir(destination), exclude);
+}
+
+
+/// Main method to handle game installation process, downloads it into the cache and then extracts
+/// Everything is done in a transaction, if the installation doesn't finish,
+/// the original files are restored
+pub fn install_game<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState
+) -> InstallResult {
+    sender.send(Message::Preparing);
+    sender.send(Message::UpdateProgressBar(0.0));
 
-    sender.send(Message::ExtractingSpr);
-    extract_archive(
-        sender,
-        app_state,
-        &spr_temp_file,
-        &destination.join("spritepacks")
-    )?;
     if app_state.lock().unwrap().get_abort_flag() {
         return Ok(());
     }
-    sleep();
 
-    cleanup(sender, mas_temp_file, spr_temp_file);
+    // Upgrades may migrate the persistent, keep a copy the user can go back to
+    sender.send(Message::BackingUp);
+    match saves::backup_saves(&get_destination(app_state))? {
+        Some(backup_path) => println!("Backed up saves to {}", backup_path.display()),
+        None => println!("Nothing to back up, skipping")
+    };
+
+    let extraction_dir = app_state.lock().unwrap().get_extraction_dir().clone();
+    let mut transaction = Transaction::new(&extraction_dir)?;
+    keep_vanilla_files(&mut transaction, &get_destination(app_state));
 
-    return Ok(());
+    // The user has the archives, no need to download anything
+    let (local_mas_archive, local_spr_archive) = {
+        let app_state = app_state.lock().unwrap();
+        (app_state.get_local_mas_archive().cloned(), app_state.get_local_spr_archive().cloned())
+    };
+    let rv = match local_mas_archive {
+        Some(mas_archive) => install_game_offline(
+            sender,
+            app_state,
+            &mut transaction,
+            &mas_archive,
+            local_spr_archive.as_deref()
+        ),
+        None => install_game_online(
+            sender,
+            app_state,
+            &mut transaction,
+            local_spr_archive.as_deref()
+        )
+    };
+
+    // We didn't get to Done, either an error or an abort
+    if !transaction.is_committed() {
+        sender.send(Message::RollingBack);
+        let failed = transaction.rollback();
+        app_state.lock().unwrap().set_rollback_failed(Some(failed));
+        if failed != 0 {
+            eprintln!("Failed to restore {failed} file(s)");
+        }
+    }
+
+    return rv;
+}
+
+/// Requests the list of releases in a thread, the tags are saved into the app state
+/// Sends ReleasesFetched once done, failing is fine - the user still can install the latest release
+pub fn fetch_release_tags_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState
+) -> thread::JoinHandle<()> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || {
+            let source = app_state.lock().unwrap().get_release_source().clone();
+            let tags = build_client().and_then(|client| get_release_tags(&client, &source));
+            match tags {
+                Ok(tags) => {
+                    app_state.lock().unwrap().set_release_tags(tags);
+                    sender.send(Message::ReleasesFetched);
+                },
+                Err(e) => eprintln!("Failed to fetch the list of releases: {e}")
+            };
+        }
+    );
+}
+
+/// Threaded version of find_installed_tag, looks up the release of the installed MAS
+/// Sends InstalledTagFound once done, the result is returned from the thread
+pub fn find_installed_tag_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState
+) -> thread::JoinHandle<Result<Option<String>, InstallError>> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || {
+            let mas_dir = get_destination(&app_state);
+            let rv = find_installed_tag(&app_state, &mas_dir);
+            sender.send(Message::InstalledTagFound);
+            return rv;
+        }
+    );
 }
 
 /// Threaded version of install_game
@@ -537,7 +2096,7 @@ pub fn install_game_in_thread(
 
     return thread::spawn(
         move || -> InstallResult {
-            return match install_game(sender, &app_state) {
+            return match install_game(&sender, &app_state) {
                 Err(e) => {
                     sender.send(Message::Error);
                     Err(e)
@@ -547,3 +2106,278 @@ pub fn install_game_in_thread(
         }
     );
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+    struct NullSender;
+
+    impl MessageSender for NullSender {
+        fn send(&self, _msg: Message) {}
+    }
+
+    /// Writes an archive with the given entries into a temp file
+    fn create_archive<'a>(entries: impl Iterator<Item = (String, &'a [u8])>, method: CompressionMethod) -> File {
+        let mut zip = ZipWriter::new(tempfile::tempfile().unwrap());
+        let options = FileOptions::default().compression_method(method);
+        for (name, data) in entries {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        let mut file = zip.finish().unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        return file;
+    }
+
+    /// Patches the raw bytes of the given archive
+    fn patch_archive(file: &mut File, patch: impl FnOnce(&mut Vec<u8>)) {
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut data).unwrap();
+        patch(&mut data);
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&data).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+    }
+
+    fn check_limits(archive: &File) -> Result<ContentSize, ExtractionError> {
+        return check_extraction_limits(&mut ZipArchive::new(archive).unwrap());
+    }
+
+    /// Returns the JSON of a release with all the assets we need
+    fn get_release_json(tag: &str, prerelease: bool) -> String {
+        let version = tag.trim_start_matches('v');
+        let asset = |name: String| format!(
+            r#"{{"name": "{name}", "size": 1, "browser_download_url": "https://github.com/Monika-After-Story/MonikaModDev/releases/download/{tag}/{name}"}}"#
+        );
+        return format!(
+            r#"{{"tag_name": "{tag}", "name": "{tag}", "prerelease": {prerelease}, "assets": [{}, {}, {}]}}"#,
+            asset(format!("Monika_After_Story-{version}-Mod.zip")),
+            asset(format!("Monika_After_Story-{version}-Mod-Dlx.zip")),
+            asset("spritepacks.zip".to_string())
+        );
+    }
+
+    #[test]
+    fn test_get_installable_tags() {
+        let json = format!(
+            "[{}, {}, {}]",
+            get_release_json("v0.13.0-rc1", true),
+            get_release_json("v0.12.15", false),
+            get_release_json("v0.12.14", false)
+        );
+        let releases: Vec<GHRelease> = serde_json::from_str(&json).unwrap();
+        assert_eq!(get_installable_tags(releases).collect::<Vec<String>>(), vec!["v0.12.15", "v0.12.14"]);
+    }
+
+    #[test]
+    fn test_get_installable_tags_no_flags() {
+        // Mirrors might not have the flags, they are optional
+        let json = format!("[{}]", get_release_json("v0.12.15", false).replace(r#""prerelease": false, "#, ""));
+        let releases: Vec<GHRelease> = serde_json::from_str(&json).unwrap();
+        assert_eq!(get_installable_tags(releases).collect::<Vec<String>>(), vec!["v0.12.15"]);
+    }
+
+    #[test]
+    fn test_limits_ok() {
+        // Compresses well, but it's too small to be a bomb
+        let data = vec![0; 512*1024];
+        let archive = create_archive([("zeros".to_string(), &data[..])].into_iter(), CompressionMethod::Deflated);
+        assert_eq!(check_limits(&archive).unwrap(), 512*1024);
+    }
+
+    #[test]
+    fn test_limits_too_many_entries() {
+        let entries = (0..=MAX_ARCHIVE_ENTRIES).map(|i| (i.to_string(), &b""[..]));
+        let archive = create_archive(entries, CompressionMethod::Stored);
+        assert!(matches!(check_limits(&archive), Err(ExtractionError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_limits_too_large() {
+        let entries = (0..5).map(|i| (i.to_string(), &b"data"[..]));
+        let mut archive = create_archive(entries, CompressionMethod::Stored);
+        // Make every entry claim almost 4 GiB, so the total is over the limit, the ratio is fine
+        patch_archive(
+            &mut archive,
+            |data| {
+                let signature = 0x02014b50_u32.to_le_bytes();
+                let offsets: Vec<usize> = data.windows(4)
+                    .enumerate()
+                    .filter(|(_, window)| *window == signature)
+                    .map(|(i, _)| i)
+                    .collect();
+                assert_eq!(offsets.len(), 5);
+                for offset in offsets {
+                    // The compressed and uncompressed sizes in the central directory record
+                    data[offset+20..offset+24].copy_from_slice(&0xFFFFFFFE_u32.to_le_bytes());
+                    data[offset+24..offset+28].copy_from_slice(&0xFFFFFFFE_u32.to_le_bytes());
+                }
+            }
+        );
+        assert!(matches!(check_limits(&archive), Err(ExtractionError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_limits_compression_ratio() {
+        let data = vec![0; MIN_ENTRY_SIZE_FOR_RATIO as usize];
+        let archive = create_archive([("zeros".to_string(), &data[..])].into_iter(), CompressionMethod::Deflated);
+        assert!(matches!(check_limits(&archive), Err(ExtractionError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_copy_entry() {
+        let mut written = Vec::new();
+        let mut progress = 0;
+        copy_entry(&mut io::Cursor::new(b"0123456789"), 10, &mut written, |read| progress += read).unwrap();
+        assert_eq!(written, b"0123456789");
+        assert_eq!(progress, 10);
+    }
+
+    #[test]
+    fn test_copy_entry_more_data() {
+        let rv = copy_entry(&mut io::Cursor::new(b"0123456789"), 5, &mut io::sink(), |_| {});
+        assert!(matches!(rv, Err(ExtractionError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_archive_corrupted_entry() {
+        let mut archive = create_archive([("script.rpy".to_string(), &b"original data"[..])].into_iter(), CompressionMethod::Stored);
+        // Stored data is as is, so we can change it without breaking the headers
+        patch_archive(
+            &mut archive,
+            |data| {
+                let offset = data.windows(13).position(|window| window == b"original data").unwrap();
+                data[offset] = b'O';
+            }
+        );
+        let app_state = Arc::new(Mutex::new(AppState::new(PathBuf::new(), false, false, false, 0.0)));
+        let rv = test_archive(&NullSender, &app_state, &archive);
+        assert!(matches!(rv, Err(ExtractionError::CorruptedEntry(name, _)) if name == "script.rpy"));
+    }
+
+    const SHA256SUMS: &str = "\
+        5F1C3B8A9E2D4C6B7A8F9E0D1C2B3A4F5E6D7C8B9A0F1E2D3C4B5A6F7E8D9C0B  Monika_After_Story-0.12.15-Mod.zip\n\
+        0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9 *spritepacks.zip\n\
+        \n\
+        not a checksum line\n";
+
+    /// Returns the data we pretend to download and its SHA-256
+    fn get_download_data() -> (Vec<u8>, String) {
+        let data: Vec<u8> = (0..100_000_u32).map(|i| (i % 251) as u8).collect();
+        let sha256 = format!("{:x}", Sha256::digest(&data));
+        return (data, sha256);
+    }
+
+    #[test]
+    fn test_parse_checksums() {
+        let checksums = parse_checksums(SHA256SUMS);
+        assert_eq!(checksums.len(), 2);
+        // Hashes are lowercased, so they compare the same way as the ones from digests
+        assert_eq!(
+            checksums["Monika_After_Story-0.12.15-Mod.zip"],
+            "5f1c3b8a9e2d4c6b7a8f9e0d1c2b3a4f5e6d7c8b9a0f1e2d3c4b5a6f7e8d9c0b"
+        );
+        assert_eq!(
+            checksums["spritepacks.zip"],
+            "0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9"
+        );
+    }
+
+    #[test]
+    fn test_expected_sha256() {
+        let asset = |digest: &str| -> GHAsset {
+            let json = format!(
+                r#"{{"name": "spritepacks.zip", "size": 1, "browser_download_url": "https://example.com/spritepacks.zip"{digest}}}"#
+            );
+            return serde_json::from_str(&json).unwrap();
+        };
+        let with_digest = asset(r#", "digest": "sha256:abcdef""#);
+        let without_digest = asset("");
+        assert_eq!(with_digest.get_sha256(), Some("abcdef"));
+        assert_eq!(without_digest.get_sha256(), None);
+        assert_eq!(asset(r#", "digest": "md5:abcdef""#).get_sha256(), None);
+
+        let data = ReleaseData::new(
+            "v0.12.15".to_string(),
+            "v0.12.15".to_string(),
+            asset(""),
+            asset(""),
+            asset(""),
+            parse_checksums(SHA256SUMS)
+        );
+        // The digest takes priority over the checksums asset
+        assert_eq!(data.get_expected_sha256(&with_digest), Some("abcdef"));
+        assert_eq!(
+            data.get_expected_sha256(&without_digest),
+            Some("0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9")
+        );
+    }
+
+    #[test]
+    fn test_resumed_download_sha256() {
+        let (data, sha256) = get_download_data();
+        let mut file = tempfile::tempfile().unwrap();
+        // The previous run stopped mid-way
+        file.write_all(&data[..40_000]).unwrap();
+
+        let mut hasher = Some(Sha256::new());
+        assert_eq!(resume_download(&mut file, hasher.as_mut()).unwrap(), 40_000);
+        let mut writer = HashingWriter { inner: &mut file, hasher: hasher.as_mut() };
+        writer.write_all(&data[40_000..]).unwrap();
+
+        verify_sha256(&mut file, hasher, Some(&sha256)).unwrap();
+        let mut written = Vec::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_end(&mut written).unwrap();
+        assert_eq!(written, data);
+    }
+
+    #[test]
+    fn test_verify_sha256_mismatch() {
+        let (data, _) = get_download_data();
+        let mut file = tempfile::tempfile().unwrap();
+        let mut hasher = Sha256::new();
+        HashingWriter { inner: &mut file, hasher: Some(&mut hasher) }.write_all(&data).unwrap();
+
+        let rv = verify_sha256(&mut file, Some(hasher), Some(&"0".repeat(64)));
+        assert!(matches!(rv, Err(DownloadError::ChecksumMismatch(_, _))));
+        // The next run must not resume the corrupted data
+        assert_eq!(file.metadata().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_download_to_file_complete_part() {
+        // The whole file is there from the previous run, so nothing is requested
+        let (data, sha256) = get_download_data();
+        let client = build_client().unwrap();
+        let app_state = Arc::new(Mutex::new(AppState::new(PathBuf::new(), false, false, false, 0.0)));
+        let download = |expected_sha256: &str| {
+            let mut file = tempfile::tempfile().unwrap();
+            file.write_all(&data).unwrap();
+            return download_to_file(
+                &client,
+                &NullSender,
+                &app_state,
+                "https://example.com/spritepacks.zip",
+                Some(data.len() as ContentSize),
+                Some(expected_sha256),
+                &mut file
+            );
+        };
+        assert!(download(&sha256).is_ok());
+        assert!(matches!(download(&"0".repeat(64)), Err(DownloadError::ChecksumMismatch(_, _))));
+    }
+
+    #[test]
+    fn test_get_archive_version() {
+        assert_eq!(get_archive_version(Path::new("/tmp/Monika_After_Story-0.12.15-Mod.zip")).as_deref(), Some("0.12.15"));
+        assert_eq!(get_archive_version(Path::new("Monika_After_Story-0.12.15-Mod-Dlx.zip")).as_deref(), Some("0.12.15"));
+        assert_eq!(get_archive_version(Path::new("spritepacks.zip")), None);
+        assert_eq!(get_archive_version(Path::new("Monika_After_Story-latest-Mod.zip")), None);
+    }
+}
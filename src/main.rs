@@ -3,13 +3,26 @@
 
 mod app;
 mod audio;
+mod cache;
+mod cli;
+mod compat;
+mod discovery;
 mod errors;
 mod installer;
+mod manifest;
+mod repair;
+mod saves;
 mod static_data;
+mod transaction;
+mod uninstaller;
+mod version;
 mod utils;
 
 
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    process
+};
 
 use regex::Regex;
 use lazy_static::lazy_static;
@@ -24,9 +37,13 @@ const DEF_VERSION: &str = "unknown build";
 // GH link parts to accept the API
 const ORG_NAME: &str = "Monika-After-Story";
 const REPO_NAME: &str = "MonikaModDev";
+const DEF_API_URL: &str = "https://api.github.com";
+const DEF_REPO_SLUG: &str = const_format::formatcp!("{ORG_NAME}/{REPO_NAME}");
+// Env vars to override the above, allow to use mirrors and forks
+const API_URL_ENV_VAR: &str = "MAS_INSTALLER_API_URL";
+const REPO_SLUG_ENV_VAR: &str = "MAS_INSTALLER_REPO";
 
 const CREDITS_URL: &str = "https://www.youtube.com/user/MyNewSoundtrack";
-const CHANGELOG_URL: &str = const_format::formatcp!("https://github.com/{ORG_NAME}/{REPO_NAME}/releases/latest");
 
 
 lazy_static! {
@@ -39,6 +56,9 @@ lazy_static! {
         hm
     };
 
+    /// The regex pattern for the optional release asset with SHA-256 checksums
+    pub static ref CHECKSUMS_ASSET_NAME_RE: Regex = Regex::new(r"^(?i)(sha256sums|checksums)(\.sha256|\.txt)?$").unwrap();
+
     /// The headers we use to access GH API
     pub static ref HEADERS: HeaderMap = {
         let mut h = HeaderMap::new();
@@ -53,6 +73,12 @@ lazy_static! {
 
 /// The entry point
 fn main() {
+    // Run headless if we got any arguments
+    if cli::has_args() {
+        utils::attach_parent_console();
+        process::exit(cli::run());
+    }
+
     // This needs to be done first
     utils::disable_global_hotkeys();
     // Builds the app
@@ -0,0 +1,119 @@
+/// The module that implements the install manifest
+/// The manifest is written into the game dir after a successful installation,
+/// it records what has been installed and where
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::installer::ContentSize;
+
+
+const MANIFEST_FILE_NAME: &str = ".mas_install.json";
+
+
+/// The variant of MAS that has been installed
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    Default,
+    Deluxe
+}
+
+/// Represents a file extracted from an archive
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ManifestEntry {
+    // The path inside the archive, always uses forward slashes
+    pub path: String,
+    pub size: ContentSize,
+    pub crc32: u32
+}
+
+/// Represents an installation of MAS
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    // The version from the archive name for local archives (0.12.15 vs v0.12.15),
+    // None if the release is unknown
+    pub tag: Option<String>,
+    pub variant: Variant,
+    pub spritepacks: bool,
+    pub installer_version: String,
+    // Unix time of the installation
+    pub timestamp: u64,
+    // Relative to the MAS directory
+    pub files: Vec<ManifestEntry>,
+    // Relative to the spritepacks directory, or to the MAS directory
+    // if the spritepacks were placed into the game dir
+    pub spritepack_files: Vec<ManifestEntry>,
+    #[serde(default)]
+    pub spritepacks_in_game: bool
+}
+
+impl Manifest {
+    /// Creates a new manifest, the version and the time are filled in automatically
+    pub fn new(
+        tag: Option<String>,
+        variant: Variant,
+        files: Vec<ManifestEntry>,
+        spritepack_files: Vec<ManifestEntry>,
+        spritepacks_in_game: bool
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        return Self {
+            tag,
+            variant,
+            spritepacks: !spritepack_files.is_empty(),
+            installer_version: crate::VERSION.unwrap_or(crate::DEF_VERSION).to_string(),
+            timestamp,
+            files,
+            spritepack_files,
+            spritepacks_in_game
+        };
+    }
+
+    /// Returns the files relative to the MAS directory,
+    /// including the spritepacks placed into the game dir
+    pub fn get_mas_dir_files(&self) -> impl Iterator<Item = &ManifestEntry> {
+        let spritepack_files = match self.spritepacks_in_game {
+            true => self.spritepack_files.as_slice(),
+            false => &[]
+        };
+        return self.files.iter().chain(spritepack_files.iter());
+    }
+
+    /// Returns the files relative to the spritepacks directory
+    pub fn get_spritepacks_dir_files(&self) -> &[ManifestEntry] {
+        return match self.spritepacks_in_game {
+            true => &[],
+            false => self.spritepack_files.as_slice()
+        };
+    }
+
+    /// Reads the manifest from the given file
+    pub fn load(path: &Path) -> Result<Self, io::Error> {
+        let file = File::open(path)?;
+        return Ok(serde_json::from_reader(file)?);
+    }
+
+    /// Writes the manifest into the given file
+    pub fn save(&self, path: &Path) -> Result<(), io::Error> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        return Ok(());
+    }
+}
+
+
+/// Returns the path to the manifest for the given MAS directory
+pub fn get_manifest_path(mas_dir: &Path) -> PathBuf {
+    return mas_dir.join("game").join(MANIFEST_FILE_NAME);
+}
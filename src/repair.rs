@@ -0,0 +1,320 @@
+/// The module that implements verification and repair of an installation
+/// The files are compared with the release archive, the broken ones can be re-extracted
+
+use std::{
+    collections::HashSet,
+    fs::File,
+    path::Path,
+    thread
+};
+
+use fltk::app::Sender;
+
+use crate::{
+    app::{
+        state::ThreadSafeState,
+        Message
+    },
+    errors::InstallError,
+    installer::{self, InstallResult, MessageSender},
+    manifest::{self, Manifest, Variant},
+    transaction::Transaction
+};
+
+
+/// Represents the result of a verification
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    pub missing: Vec<String>,
+    pub modified: Vec<String>
+}
+
+impl VerifyReport {
+    /// Returns true if all the files are intact
+    pub fn is_ok(&self) -> bool {
+        return self.missing.is_empty() && self.modified.is_empty();
+    }
+
+    /// Returns the broken files, the missing ones first
+    pub fn get_broken_files(&self) -> impl Iterator<Item = &String> {
+        return self.missing.iter().chain(self.modified.iter());
+    }
+}
+
+
+/// Returns the variant of the installation, makes the app state use the installed release
+/// The release the user selected takes priority
+fn select_installed_release(app_state: &ThreadSafeState, mas_dir: &Path) -> Result<Variant, InstallError> {
+    let manifest = Manifest::load(&manifest::get_manifest_path(mas_dir)).ok();
+
+    if app_state.lock().unwrap().get_release_tag().is_none() {
+        // The installed version isn't always a tag (0.12.15 vs v0.12.15), even in the manifest
+        let tag = installer::find_installed_tag(app_state, mas_dir)?;
+        match tag {
+            Some(ref tag) => println!("Verifying against release {tag}..."),
+            None => println!("Can't tell the installed version, verifying against the latest release...")
+        };
+        app_state.lock().unwrap().set_release_tag(tag);
+    }
+
+    return Ok(
+        match manifest {
+            Some(manifest) => manifest.variant,
+            None => match app_state.lock().unwrap().get_deluxe_ver_flag() {
+                true => Variant::Deluxe,
+                false => Variant::Default
+            }
+        }
+    );
+}
+
+/// Represents the archive we verify against
+struct ReleaseArchive {
+    file: File,
+    // The version from the name for local archives
+    tag: Option<String>,
+    variant: Variant
+}
+
+/// Returns the archive of the installed release, downloads it if needed
+fn get_release_archive<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    mas_dir: &Path
+) -> Result<ReleaseArchive, InstallError> {
+    let local_mas_archive = app_state.lock().unwrap().get_local_mas_archive().cloned();
+    if let Some(mas_archive) = local_mas_archive {
+        let variant = match installer::get_archive_kind(&mas_archive) {
+            Some("dlx_ver") => Variant::Deluxe,
+            _ => Variant::Default
+        };
+        let tag = installer::get_archive_version(&mas_archive);
+        return Ok(ReleaseArchive { file: File::open(mas_archive)?, tag, variant });
+    }
+
+    let variant = select_installed_release(app_state, mas_dir)?;
+    let (file, tag) = installer::download_mas_archive(sender, app_state, variant)?;
+    return Ok(ReleaseArchive { file, tag: Some(tag), variant });
+}
+
+/// Compares the files in the MAS dir with the entries of the archive
+fn verify_files<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    archive: &File,
+    mas_dir: &Path
+) -> Result<VerifyReport, InstallError> {
+    sender.send(Message::Verifying);
+    sender.send(Message::UpdateProgressBar(0.0));
+
+    let mut report = VerifyReport::default();
+    let entries = installer::get_archive_entries(archive)?;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let path = mas_dir.join(&entry.path);
+        if !path.is_file() {
+            report.missing.push(entry.path.clone());
+        }
+        else if !installer::is_same_file(&path, entry.size, entry.crc32) {
+            report.modified.push(entry.path.clone());
+        }
+
+        let pb_val = (i as f64 + 1.0) / entries.len() as f64;
+        sender.send(Message::UpdateProgressBar(pb_val));
+
+        if app_state.lock().unwrap().get_abort_flag() {
+            break;
+        }
+    }
+
+    return Ok(report);
+}
+
+
+/// Verifies the installation in the MAS dir, the report is saved into the app state
+/// Sends Verified once done
+pub fn verify_game<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState
+) -> Result<VerifyReport, InstallError> {
+    sender.send(Message::Preparing);
+    sender.send(Message::UpdateProgressBar(0.0));
+
+    let mas_dir = installer::get_destination(app_state);
+    let archive = get_release_archive(sender, app_state, &mas_dir)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(VerifyReport::default());
+    }
+
+    let report = verify_files(sender, app_state, &archive.file, &mas_dir)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(report);
+    }
+
+    app_state.lock().unwrap().set_verify_report(Some(report.clone()));
+    sender.send(Message::Verified);
+
+    return Ok(report);
+}
+
+/// Re-extracts the broken files of the installation in the MAS dir
+/// Everything is done in a transaction, if the repair doesn't finish,
+/// the original files are restored
+pub fn repair_game<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    report: &VerifyReport
+) -> InstallResult {
+    sender.send(Message::Preparing);
+    sender.send(Message::UpdateProgressBar(0.0));
+
+    let mas_dir = installer::get_destination(app_state);
+    let archive = get_release_archive(sender, app_state, &mas_dir)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
+
+    let extraction_dir = app_state.lock().unwrap().get_extraction_dir().clone();
+    let mut transaction = Transaction::new(&extraction_dir)?;
+
+    let filter: HashSet<String> = report.get_broken_files().cloned().collect();
+    sender.send(Message::Repairing);
+    let rv = installer::extract_archive(
+        sender,
+        app_state,
+        &mut transaction,
+        &archive.file,
+        &mas_dir,
+        Some(&filter)
+    )
+    .map_err(InstallError::from)
+    .and_then(|_| write_manifest(&mut transaction, &archive, &mas_dir));
+
+    if rv.is_ok() && !app_state.lock().unwrap().get_abort_flag() {
+        transaction.commit();
+        sender.send(Message::Repaired);
+    }
+    else {
+        sender.send(Message::RollingBack);
+        let failed = transaction.rollback();
+        app_state.lock().unwrap().set_rollback_failed(Some(failed));
+        if failed != 0 {
+            eprintln!("Failed to restore {failed} file(s)");
+        }
+    }
+
+    return rv;
+}
+
+/// Writes the manifest of the repaired installation, the files now match the archive
+/// The spritepacks aren't repaired, so their files are kept from the old manifest
+fn write_manifest(transaction: &mut Transaction, archive: &ReleaseArchive, mas_dir: &Path) -> InstallResult {
+    let files = installer::get_archive_entries(&archive.file)?;
+    let (spritepack_files, spritepacks_in_game) = match Manifest::load(&manifest::get_manifest_path(mas_dir)) {
+        Ok(manifest) => (manifest.spritepack_files, manifest.spritepacks_in_game),
+        Err(_) => (Vec::new(), false)
+    };
+    let manifest = Manifest::new(archive.tag.clone(), archive.variant, files, spritepack_files, spritepacks_in_game);
+    installer::write_manifest(transaction, mas_dir, &manifest)?;
+    return Ok(());
+}
+
+
+/// Threaded version of verify_game
+pub fn verify_game_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState
+) -> thread::JoinHandle<InstallResult> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || -> InstallResult {
+            return match verify_game(&sender, &app_state) {
+                Err(e) => {
+                    sender.send(Message::VerifyFailed);
+                    Err(e)
+                },
+                Ok(_) => Ok(())
+            };
+        }
+    );
+}
+
+/// Threaded version of repair_game
+pub fn repair_game_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState,
+    report: VerifyReport
+) -> thread::JoinHandle<InstallResult> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || -> InstallResult {
+            return match repair_game(&sender, &app_state, &report) {
+                Err(e) => {
+                    sender.send(Message::VerifyFailed);
+                    Err(e)
+                },
+                Ok(_) => Ok(())
+            };
+        }
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        io::Write,
+        sync::{Arc, Mutex}
+    };
+    use zip::{write::FileOptions, ZipWriter};
+    use crate::app::state::AppState;
+
+    struct NullSender;
+
+    impl MessageSender for NullSender {
+        fn send(&self, _msg: Message) {}
+    }
+
+    #[test]
+    fn test_repair_writes_manifest() {
+        let root = tempfile::tempdir().unwrap();
+        let mas_dir = root.path();
+        fs::create_dir(mas_dir.join("game")).unwrap();
+        fs::write(mas_dir.join("game/script.rpyc"), b"broken").unwrap();
+        // The old manifest is from an older release, the spritepacks stay as they are
+        let spritepack_entry = manifest::ManifestEntry { path: "mod_assets/sprite.png".to_string(), size: 1, crc32: 0 };
+        Manifest::new(Some("v0.12.14".to_string()), Variant::Default, Vec::new(), vec![spritepack_entry], true)
+            .save(&manifest::get_manifest_path(mas_dir))
+            .unwrap();
+
+        let archives_dir = tempfile::tempdir().unwrap();
+        let archive_path = archives_dir.path().join("Monika_After_Story-0.12.15-Mod-Dlx.zip");
+        let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+        zip.start_file("game/script.rpyc", FileOptions::default()).unwrap();
+        zip.write_all(b"mas").unwrap();
+        zip.finish().unwrap();
+
+        let app_state = Arc::new(Mutex::new(AppState::new(mas_dir.to_path_buf(), false, false, false, 0.0)));
+        app_state.lock().unwrap().set_local_mas_archive(Some(archive_path));
+        let report = verify_game(&NullSender, &app_state).unwrap();
+        assert_eq!(report.modified, vec!["game/script.rpyc"]);
+        repair_game(&NullSender, &app_state, &report).unwrap();
+
+        assert_eq!(fs::read(mas_dir.join("game/script.rpyc")).unwrap(), b"mas");
+        let manifest = Manifest::load(&manifest::get_manifest_path(mas_dir)).unwrap();
+        assert_eq!(manifest.tag.as_deref(), Some("0.12.15"));
+        assert_eq!(manifest.variant, Variant::Deluxe);
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.files[0].path, "game/script.rpyc");
+        assert_eq!(manifest.files[0].crc32, crc32fast::hash(b"mas"));
+        assert_eq!(manifest.spritepack_files.len(), 1);
+        assert!(manifest.spritepacks_in_game);
+        assert!(crate::transaction::find_leftover_staging_dirs(mas_dir).is_empty());
+    }
+}
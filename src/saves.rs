@@ -0,0 +1,292 @@
+/// The module that implements backups of the MAS persistent and saves
+/// The backups are zip archives in the installer data dir, one per installation
+
+use std::{
+    env,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use zip::{
+    write::FileOptions,
+    CompressionMethod,
+    ZipArchive,
+    ZipWriter
+};
+
+use crate::{
+    errors::BackupError,
+    transaction::Transaction,
+    utils
+};
+
+
+/// The name of the Ren'Py save dir MAS uses (config.save_directory)
+const SAVE_DIR_NAME: &str = "Monika After Story";
+const BACKUPS_DIR_NAME: &str = "backups";
+const BACKUP_PREFIX: &str = "persistent-";
+const BACKUP_EXT: &str = ".zip";
+/// The number of the most recent backups we keep, the older ones are removed
+const MAX_BACKUPS: usize = 10;
+
+/// Prefixes of the entries in the backup archive
+const SAVE_DIR_PREFIX: &str = "savedir/";
+const GAME_SAVES_PREFIX: &str = "game_saves/";
+
+
+/// Represents a backup on the disk
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64
+}
+
+
+/// Returns the dir Ren'Py keeps the persistent and saves of MAS in
+pub fn get_save_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let renpy_dir = match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(|appdata| PathBuf::from(appdata).join("RenPy")),
+        "macos" => home.map(|home| home.join("Library/RenPy")),
+        _ => home.map(|home| home.join(".renpy"))
+    };
+    return renpy_dir.map(|dir| dir.join(SAVE_DIR_NAME));
+}
+
+/// Returns the dir with the saves Ren'Py keeps next to the game
+fn get_game_saves_dir(mas_dir: &Path) -> PathBuf {
+    return mas_dir.join("game/saves");
+}
+
+/// Returns the dir with the backups
+pub fn get_backups_dir() -> PathBuf {
+    return utils::get_data_dir().join(BACKUPS_DIR_NAME);
+}
+
+/// Returns the dirs to back up with their prefixes in the archive
+fn get_backup_sources(mas_dir: &Path) -> Vec<(PathBuf, &'static str)> {
+    let mut sources = Vec::new();
+    if let Some(save_dir) = get_save_dir() {
+        sources.push((save_dir, SAVE_DIR_PREFIX));
+    }
+    sources.push((get_game_saves_dir(mas_dir), GAME_SAVES_PREFIX));
+    return sources;
+}
+
+/// Returns the files in the dir, recursively
+fn get_files(dir: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let mut files = Vec::new();
+    if !dir.is_dir() {
+        return Ok(files);
+    }
+
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for item in fs::read_dir(&dir)? {
+            let item_path = item?.path();
+            if item_path.is_dir() {
+                dirs.push(item_path);
+            }
+            else {
+                files.push(item_path);
+            }
+        }
+    }
+    return Ok(files);
+}
+
+
+/// Checks if there's anything to back up
+fn has_saves(mas_dir: &Path) -> bool {
+    return get_backup_sources(mas_dir).iter().any(|(dir, _)| dir.is_dir());
+}
+
+/// Creates a backup of the persistent and the saves, removes the old backups
+/// Returns the path to the backup, None if there was nothing to back up
+pub fn backup_saves(mas_dir: &Path) -> Result<Option<PathBuf>, BackupError> {
+    let backup_path = create_backup(mas_dir)?;
+    if backup_path.is_some() {
+        prune_old_backups();
+    }
+    return Ok(backup_path);
+}
+
+/// Creates a backup of the persistent and the saves, see backup_saves
+fn create_backup(mas_dir: &Path) -> Result<Option<PathBuf>, BackupError> {
+    // A fresh install has no saves, no need to even look
+    if !has_saves(mas_dir) {
+        return Ok(None);
+    }
+
+    let mut files = Vec::new();
+    for (dir, prefix) in get_backup_sources(mas_dir) {
+        for path in get_files(&dir)? {
+            // Can't fail, we're walking the dir
+            let rel_path = path.strip_prefix(&dir).unwrap().to_path_buf();
+            files.push((path, prefix, rel_path));
+        }
+    }
+    if files.is_empty() {
+        return Ok(None);
+    }
+
+    let backups_dir = get_backups_dir();
+    fs::create_dir_all(&backups_dir)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let mut backup_path = backups_dir.join(
+        format!("{BACKUP_PREFIX}{}{BACKUP_EXT}", utils::format_timestamp(timestamp))
+    );
+    // Two backups in the same second, e.g. a restore right after an install
+    let mut i = 1;
+    while backup_path.exists() {
+        backup_path = backups_dir.join(
+            format!("{BACKUP_PREFIX}{}-{i}{BACKUP_EXT}", utils::format_timestamp(timestamp))
+        );
+        i += 1;
+    }
+
+    let mut zip = ZipWriter::new(File::create(&backup_path)?);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, prefix, rel_path) in files {
+        // Zip entries always use forward slashes
+        let rel_path: Vec<String> = rel_path.components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        zip.start_file(format!("{prefix}{}", rel_path.join("/")), options)?;
+        io::copy(&mut File::open(&path)?, &mut zip)?;
+    }
+    zip.finish()?;
+
+    return Ok(Some(backup_path));
+}
+
+/// Returns the backups, the newest first
+pub fn list_backups() -> Result<Vec<Backup>, BackupError> {
+    let backups_dir = get_backups_dir();
+    if !backups_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for item in fs::read_dir(&backups_dir)? {
+        let item = item?;
+        let name = match item.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue
+        };
+        if !name.starts_with(BACKUP_PREFIX) || !name.ends_with(BACKUP_EXT) {
+            continue;
+        }
+        let metadata = item.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        backups.push(
+            Backup {
+                name: name.trim_end_matches(BACKUP_EXT).to_string(),
+                path: item.path(),
+                size: metadata.len()
+            }
+        );
+    }
+    // The names have the timestamps, so we can sort them as strings
+    backups.sort_by(|a, b| b.name.cmp(&a.name));
+
+    return Ok(backups);
+}
+
+/// Removes the oldest backups, keeps the given number of the most recent ones
+/// Returns the number of removed backups
+fn prune_backups(keep: usize) -> Result<usize, BackupError> {
+    let mut removed: usize = 0;
+    for backup in list_backups()?.into_iter().skip(keep) {
+        fs::remove_file(&backup.path)?;
+        removed += 1;
+    }
+    return Ok(removed);
+}
+
+/// Removes the backups over MAX_BACKUPS, failing here isn't a reason to stop
+fn prune_old_backups() {
+    match prune_backups(MAX_BACKUPS) {
+        Ok(0) => {},
+        Ok(removed) => println!("Removed {removed} old backup(s)"),
+        Err(e) => eprintln!("Failed to remove old backups: {e}")
+    };
+}
+
+/// Returns the backup with the given name
+pub fn find_backup(name: &str) -> Result<Option<Backup>, BackupError> {
+    let name = name.trim_end_matches(BACKUP_EXT);
+    return Ok(list_backups()?.into_iter().find(|backup| backup.name == name));
+}
+
+/// Puts the files from the given backup back, the current files are backed up first
+/// Everything is done in a transaction, if the restore fails, the current files are kept
+pub fn restore_backup(backup_path: &Path, mas_dir: &Path) -> Result<(), BackupError> {
+    let mut archive = ZipArchive::new(File::open(backup_path)?)?;
+
+    // Don't lose the current progress if the user picked the wrong backup
+    // The old backups are pruned after, we might be restoring the oldest one
+    let current_backup_path = create_backup(mas_dir)?;
+
+    let backups_dir = get_backups_dir();
+    fs::create_dir_all(&backups_dir)?;
+    let mut transaction = Transaction::new(&backups_dir)?;
+    let rv = extract_backup(&mut transaction, &mut archive, mas_dir);
+    if rv.is_ok() {
+        transaction.commit();
+    }
+    else {
+        let failed = transaction.rollback();
+        if failed != 0 {
+            eprintln!("Failed to restore {failed} file(s)");
+        }
+    }
+    if current_backup_path.is_some() {
+        prune_old_backups();
+    }
+
+    return rv;
+}
+
+/// Extracts the backup archive into the dirs it was made from
+fn extract_backup(
+    transaction: &mut Transaction,
+    archive: &mut ZipArchive<File>,
+    mas_dir: &Path
+) -> Result<(), BackupError> {
+    let sources = get_backup_sources(mas_dir);
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+
+        let file_path = file.enclosed_name()
+            .ok_or(BackupError::UnsafeFilepath(file.name().to_string()))?
+            .to_path_buf();
+        let target_path = sources.iter()
+            .find_map(|(dir, prefix)| {
+                let rel_path = file_path.strip_prefix(prefix.trim_end_matches('/')).ok()?;
+                return Some(dir.join(rel_path));
+            })
+            .ok_or(BackupError::UnsafeFilepath(file.name().to_string()))?;
+
+        if let Some(parent_dir) = target_path.parent() {
+            transaction.create_dir_all(parent_dir)?;
+        }
+        transaction.prepare_write(&target_path)?;
+        io::copy(&mut file, &mut File::create(&target_path)?)?;
+    }
+
+    return Ok(());
+}
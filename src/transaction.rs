@@ -0,0 +1,486 @@
+/// The module that implements install transactions
+/// Every file the installer overwrites or deletes is moved into a staging dir first,
+/// so the original tree can be restored if the installation doesn't finish
+/// The staging dir is <root>/.mas_installer_staging-XXXXXX, the moved files are named
+/// by their index and the journal file there maps them to the original paths,
+/// if the installer is killed mid-way, recover_staging_dir puts them back
+
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf}
+};
+
+use tempfile::TempDir;
+
+
+const STAGING_DIR_PREFIX: &str = ".mas_installer_staging-";
+/// The journal of the changes, it's removed on commit, so a staging dir without it
+/// has nothing to restore
+const JOURNAL_FILE_NAME: &str = "journal";
+// The kinds of the journal records, one record per line: "<kind>\t<path>[\t<backup name>]"
+const JOURNAL_BACKUP: &str = "backup";
+const JOURNAL_CREATED_FILE: &str = "file";
+const JOURNAL_CREATED_DIR: &str = "dir";
+
+
+/// Where to keep copies of the original files, see Transaction::keep_originals
+struct KeptOriginals {
+    root: PathBuf,
+    dir: PathBuf,
+    exclude: HashSet<PathBuf>
+}
+
+/// Represents a set of changes in a directory tree that can be rolled back
+pub struct Transaction {
+    // Keeps the original files, it's inside the root, so we can just move files there
+    staging_dir: TempDir,
+    // Lets us restore the files if we crash before commit or rollback
+    journal: Option<File>,
+    // Pairs of original paths and paths to their backups
+    backups: Vec<(PathBuf, PathBuf)>,
+    // Files that didn't exist before the transaction
+    created_files: Vec<PathBuf>,
+    // Dirs that didn't exist before the transaction, parents first
+    created_dirs: Vec<PathBuf>,
+    // All paths we already took care of
+    touched: HashSet<PathBuf>,
+    // Copies of these originals stay after the commit
+    originals: Option<KeptOriginals>,
+    is_committed: bool
+}
+
+impl Transaction {
+    /// Creates a new Transaction, the staging dir is created inside the given root
+    pub fn new(root: &Path) -> Result<Self, io::Error> {
+        let staging_dir = tempfile::Builder::new()
+            .prefix(STAGING_DIR_PREFIX)
+            .tempdir_in(root)?;
+        let journal = File::create(staging_dir.path().join(JOURNAL_FILE_NAME))?;
+
+        return Ok(
+            Self {
+                staging_dir,
+                journal: Some(journal),
+                backups: Vec::new(),
+                created_files: Vec::new(),
+                created_dirs: Vec::new(),
+                touched: HashSet::new(),
+                originals: None,
+                is_committed: false
+            }
+        );
+    }
+
+    /// Writes a record into the journal, it's flushed right away, we might crash any moment
+    /// The paths are written as UTF-8, non UTF-8 paths can't be recovered after a crash
+    fn write_journal(&mut self, kind: &str, path: &Path, backup_name: Option<&str>) -> Result<(), io::Error> {
+        let journal = match self.journal {
+            Some(ref mut journal) => journal,
+            None => return Ok(())
+        };
+        let mut record = format!("{kind}\t{}", path.to_string_lossy());
+        if let Some(backup_name) = backup_name {
+            record.push('\t');
+            record.push_str(backup_name);
+        }
+        writeln!(journal, "{record}")?;
+        return journal.sync_data();
+    }
+
+    /// Returns true if the transaction has been committed
+    pub fn is_committed(&self) -> bool {
+        return self.is_committed;
+    }
+
+    /// Returns true if the given path was created or replaced in this transaction,
+    /// changing it (e.g. its permissions) doesn't touch the original tree
+    pub fn is_new_or_replaced(&self, path: &Path) -> bool {
+        return self.touched.contains(path) || self.created_dirs.iter().any(|dir| dir == path);
+    }
+
+    /// Makes the transaction keep copies of the original files from the given root in the given dir,
+    /// unlike the backups, the copies stay after the commit
+    /// The excluded files and the files that already have a copy are skipped
+    pub fn keep_originals(&mut self, root: &Path, dir: &Path, exclude: HashSet<PathBuf>) {
+        self.originals = Some(
+            KeptOriginals {
+                root: root.to_path_buf(),
+                dir: dir.to_path_buf(),
+                exclude
+            }
+        );
+    }
+
+    /// Returns where to copy the given file to keep the original, None if we don't need to
+    fn get_original_path(&self, path: &Path) -> Option<PathBuf> {
+        let originals = self.originals.as_ref()?;
+        if !path.is_file() || originals.exclude.contains(path) || path.starts_with(&originals.dir) {
+            return None;
+        }
+        let original_path = originals.dir.join(path.strip_prefix(&originals.root).ok()?);
+        if original_path.exists() {
+            return None;
+        }
+        return Some(original_path);
+    }
+
+    /// Returns the size of the copies we'd make to keep the originals of the given files
+    pub fn get_originals_size<'a>(&self, paths: impl Iterator<Item = &'a PathBuf>) -> u64 {
+        return paths
+            .filter(|path| self.get_original_path(path).is_some())
+            .filter_map(|path| path.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+    }
+
+    /// Copies the given file into the dir with the originals if needed
+    /// The copy is a new file, so it's removed on rollback
+    fn keep_original(&mut self, path: &Path) -> Result<(), io::Error> {
+        let original_path = match self.get_original_path(path) {
+            Some(original_path) => original_path,
+            None => return Ok(())
+        };
+
+        if let Some(parent_dir) = original_path.parent() {
+            self.create_dir_all(parent_dir)?;
+        }
+        self.write_journal(JOURNAL_CREATED_FILE, &original_path, None)?;
+        fs::copy(path, &original_path)?;
+        self.created_files.push(original_path);
+        return Ok(());
+    }
+
+    /// Moves the given path into the staging dir
+    fn backup(&mut self, path: &Path) -> Result<(), io::Error> {
+        self.keep_original(path)?;
+        let backup_name = self.backups.len().to_string();
+        let backup_path = self.staging_dir.path().join(&backup_name);
+        move_path(path, &backup_path)?;
+        // If we crash before this, the file stays in the staging dir and recovery leaves it there
+        self.write_journal(JOURNAL_BACKUP, path, Some(&backup_name))?;
+        self.backups.push((path.to_path_buf(), backup_path));
+        return Ok(());
+    }
+
+    /// Must be called before writing into the given file
+    /// Backs up the file if it exists, otherwise remembers it as a new file
+    pub fn prepare_write(&mut self, path: &Path) -> Result<(), io::Error> {
+        if !self.touched.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+        if path.exists() {
+            self.backup(path)?;
+        }
+        else {
+            self.write_journal(JOURNAL_CREATED_FILE, path, None)?;
+            self.created_files.push(path.to_path_buf());
+        }
+        return Ok(());
+    }
+
+    /// Removes the given file, keeping a backup
+    pub fn remove_file(&mut self, path: &Path) -> Result<(), io::Error> {
+        if !path.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "file not found"));
+        }
+        self.touched.insert(path.to_path_buf());
+        return self.backup(path);
+    }
+
+    /// Removes the given dir with all its content, keeping a backup
+    pub fn remove_dir_all(&mut self, path: &Path) -> Result<(), io::Error> {
+        if !path.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "directory not found"));
+        }
+        self.touched.insert(path.to_path_buf());
+        return self.backup(path);
+    }
+
+    /// Creates the given dir and all its parents, remembers the ones that didn't exist
+    pub fn create_dir_all(&mut self, path: &Path) -> Result<(), io::Error> {
+        let mut new_dirs = Vec::new();
+        let mut current = Some(path);
+        while let Some(dir) = current {
+            if dir.exists() {
+                break;
+            }
+            new_dirs.push(dir.to_path_buf());
+            current = dir.parent();
+        }
+
+        for dir in new_dirs.iter().rev() {
+            self.write_journal(JOURNAL_CREATED_DIR, dir, None)?;
+        }
+        fs::create_dir_all(path)?;
+
+        self.created_dirs.extend(new_dirs.into_iter().rev());
+        return Ok(());
+    }
+
+    /// Commits the transaction, the backups are removed
+    pub fn commit(&mut self) {
+        // Nothing to restore now, even if we crash before the staging dir is removed
+        self.journal = None;
+        fs::remove_file(self.staging_dir.path().join(JOURNAL_FILE_NAME)).ok();
+        self.backups.clear();
+        self.created_files.clear();
+        self.created_dirs.clear();
+        self.is_committed = true;
+    }
+
+    /// Rolls back the transaction, restoring the original tree
+    /// This function is "best-effort" and will try to restore as much as possible
+    /// Returns the number of paths it failed to restore
+    pub fn rollback(&mut self) -> usize {
+        let mut failed: usize = 0;
+
+        for path in self.created_files.drain(..).rev() {
+            if let Err(e) = fs::remove_file(&path) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to delete '{}': {e}", path.display());
+                    failed += 1;
+                }
+            }
+        }
+
+        for (path, backup_path) in self.backups.drain(..).rev() {
+            if let Err(e) = restore_path(&backup_path, &path) {
+                eprintln!("Failed to restore '{}': {e}", path.display());
+                failed += 1;
+            }
+        }
+
+        // Only empty dirs, if something is left there, it's not ours
+        for path in self.created_dirs.drain(..).rev() {
+            fs::remove_dir(&path).ok();
+        }
+
+        return failed;
+    }
+}
+
+
+/// Returns the staging dirs left in the given root by the installer that didn't finish
+pub fn find_leftover_staging_dirs(root: &Path) -> Vec<PathBuf> {
+    let content = match fs::read_dir(root) {
+        Ok(content) => content,
+        Err(_) => return Vec::new()
+    };
+    return content
+        .filter_map(|item| item.ok())
+        .filter(|item| item.file_name().to_string_lossy().starts_with(STAGING_DIR_PREFIX))
+        .map(|item| item.path())
+        .filter(|path| path.is_dir())
+        .collect();
+}
+
+/// Rolls back the changes recorded in the journal of the given staging dir,
+/// the same way Transaction::rollback does, then removes the staging dir
+/// The dir is kept if something couldn't be restored, so the files aren't lost
+/// Returns the number of paths it failed to restore
+pub fn recover_staging_dir(staging_dir: &Path) -> Result<usize, io::Error> {
+    let mut backups = Vec::new();
+    let mut created_files = Vec::new();
+    let mut created_dirs = Vec::new();
+
+    let journal_path = staging_dir.join(JOURNAL_FILE_NAME);
+    // No journal means the transaction was committed, the backups aren't needed
+    if !journal_path.is_file() {
+        fs::remove_dir_all(staging_dir)?;
+        return Ok(0);
+    }
+    for line in BufReader::new(File::open(&journal_path)?).lines() {
+        let line = line?;
+        let mut parts = line.split('\t');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(JOURNAL_BACKUP), Some(path), Some(backup_name)) => {
+                backups.push((PathBuf::from(path), staging_dir.join(backup_name)));
+            },
+            (Some(JOURNAL_CREATED_FILE), Some(path), None) => created_files.push(PathBuf::from(path)),
+            (Some(JOURNAL_CREATED_DIR), Some(path), None) => created_dirs.push(PathBuf::from(path)),
+            // The last record might be cut off by the crash
+            _ => eprintln!("Skipping invalid journal record '{line}'")
+        };
+    }
+
+    let mut failed: usize = 0;
+    for path in created_files.iter().rev() {
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                eprintln!("Failed to delete '{}': {e}", path.display());
+                failed += 1;
+            }
+        }
+    }
+    for (path, backup_path) in backups.iter().rev() {
+        // Don't replace the current file with nothing
+        if !backup_path.exists() {
+            eprintln!("Missing the backup of '{}'", path.display());
+            failed += 1;
+            continue;
+        }
+        if let Err(e) = restore_path(backup_path, path) {
+            eprintln!("Failed to restore '{}': {e}", path.display());
+            failed += 1;
+        }
+    }
+    for path in created_dirs.iter().rev() {
+        fs::remove_dir(path).ok();
+    }
+
+    fs::remove_file(&journal_path).ok();
+    // Anything left is a file we moved, but didn't get to record
+    let is_empty = fs::read_dir(staging_dir)?.next().is_none();
+    if failed == 0 && is_empty {
+        fs::remove_dir(staging_dir)?;
+    }
+    else if !is_empty {
+        eprintln!("Some files couldn't be restored, they are kept in '{}'", staging_dir.display());
+    }
+
+    return Ok(failed);
+}
+
+
+/// Moves a file or a dir, falls back to copying if we can't rename
+fn move_path(from: &Path, to: &Path) -> Result<(), io::Error> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_path(from, to)?;
+    return remove_path(from);
+}
+
+/// Copies a file or a dir with all its content
+fn copy_path(from: &Path, to: &Path) -> Result<(), io::Error> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for item in fs::read_dir(from)? {
+            let item = item?;
+            copy_path(&item.path(), &to.join(item.file_name()))?;
+        }
+    }
+    else {
+        fs::copy(from, to)?;
+    }
+    return Ok(());
+}
+
+/// Removes a file or a dir with all its content
+fn remove_path(path: &Path) -> Result<(), io::Error> {
+    return match path.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path)
+    };
+}
+
+/// Puts the backup back to its original place, replaces anything that's there now
+fn restore_path(backup_path: &Path, path: &Path) -> Result<(), io::Error> {
+    if path.exists() {
+        remove_path(path)?;
+    }
+    if let Some(parent_dir) = path.parent() {
+        fs::create_dir_all(parent_dir)?;
+    }
+    return move_path(backup_path, path);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a tree with a couple of files
+    fn create_tree() -> TempDir {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("game")).unwrap();
+        fs::write(root.path().join("game/script.rpy"), b"original").unwrap();
+        fs::write(root.path().join("game/old.rpy"), b"old").unwrap();
+        return root;
+    }
+
+    /// Makes the changes an install would: replaces a file, removes one and adds a new dir with a file
+    fn make_changes(transaction: &mut Transaction, root: &Path) {
+        let script_path = root.join("game/script.rpy");
+        transaction.prepare_write(&script_path).unwrap();
+        fs::write(&script_path, b"replaced").unwrap();
+
+        transaction.remove_file(&root.join("game/old.rpy")).unwrap();
+
+        let new_dir = root.join("game/mod_assets/images");
+        transaction.create_dir_all(&new_dir).unwrap();
+        let new_path = new_dir.join("new.png");
+        transaction.prepare_write(&new_path).unwrap();
+        fs::write(&new_path, b"new").unwrap();
+    }
+
+    /// Asserts the tree is the same as create_tree left it
+    fn assert_original_tree(root: &Path) {
+        assert_eq!(fs::read(root.join("game/script.rpy")).unwrap(), b"original");
+        assert_eq!(fs::read(root.join("game/old.rpy")).unwrap(), b"old");
+        assert!(!root.join("game/mod_assets").exists());
+        assert!(find_leftover_staging_dirs(root).is_empty());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let root = create_tree();
+        let mut transaction = Transaction::new(root.path()).unwrap();
+        make_changes(&mut transaction, root.path());
+        assert_eq!(find_leftover_staging_dirs(root.path()).len(), 1);
+        assert!(transaction.is_new_or_replaced(&root.path().join("game/script.rpy")));
+        assert!(transaction.is_new_or_replaced(&root.path().join("game/mod_assets/images")));
+        assert!(!transaction.is_new_or_replaced(&root.path().join("game")));
+
+        assert_eq!(transaction.rollback(), 0);
+        drop(transaction);
+        assert_original_tree(root.path());
+    }
+
+    #[test]
+    fn test_commit() {
+        let root = create_tree();
+        let mut transaction = Transaction::new(root.path()).unwrap();
+        make_changes(&mut transaction, root.path());
+
+        transaction.commit();
+        assert!(transaction.is_committed());
+        drop(transaction);
+        assert_eq!(fs::read(root.path().join("game/script.rpy")).unwrap(), b"replaced");
+        assert!(!root.path().join("game/old.rpy").exists());
+        assert_eq!(fs::read(root.path().join("game/mod_assets/images/new.png")).unwrap(), b"new");
+        assert!(find_leftover_staging_dirs(root.path()).is_empty());
+    }
+
+    #[test]
+    fn test_recover_after_crash() {
+        let root = create_tree();
+        let mut transaction = Transaction::new(root.path()).unwrap();
+        make_changes(&mut transaction, root.path());
+        // Neither commit nor rollback nor drop happen when the process is killed
+        std::mem::forget(transaction);
+
+        let staging_dirs = find_leftover_staging_dirs(root.path());
+        assert_eq!(staging_dirs.len(), 1);
+        assert_eq!(recover_staging_dir(&staging_dirs[0]).unwrap(), 0);
+        assert_original_tree(root.path());
+    }
+
+    #[test]
+    fn test_recover_after_commit() {
+        let root = create_tree();
+        let mut transaction = Transaction::new(root.path()).unwrap();
+        make_changes(&mut transaction, root.path());
+        transaction.commit();
+        std::mem::forget(transaction);
+
+        // The journal is gone, so there's nothing to restore
+        let staging_dirs = find_leftover_staging_dirs(root.path());
+        assert_eq!(staging_dirs.len(), 1);
+        assert_eq!(recover_staging_dir(&staging_dirs[0]).unwrap(), 0);
+        assert_eq!(fs::read(root.path().join("game/script.rpy")).unwrap(), b"replaced");
+        assert!(find_leftover_staging_dirs(root.path()).is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+/// The module that implements uninstallation of MAS
+/// The files are removed using the install manifest, the vanilla files
+/// replaced during the installation are restored from the backup
+
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    thread
+};
+
+use fltk::app::Sender;
+
+use crate::{
+    app::{
+        state::ThreadSafeState,
+        Message
+    },
+    errors::InstallError,
+    installer::{self, InstallResult, MessageSender},
+    manifest::{self, Manifest, ManifestEntry, Variant},
+    transaction::Transaction
+};
+
+
+const BACKUP_DIR_NAME: &str = ".mas_installer_backup";
+const VANILLA_DIR_NAME: &str = "vanilla";
+/// The layout of vanilla DDLC, the paths use forward slashes like the archive entries
+const VANILLA_DIRS: [&str; 3] = ["characters", "lib", "renpy"];
+const VANILLA_FILES: [&str; 8] = [
+    "DDLC.exe",
+    "DDLC.py",
+    "DDLC.sh",
+    "README.html",
+    "game/audio.rpa",
+    "game/fonts.rpa",
+    "game/images.rpa",
+    "game/scripts.rpa"
+];
+
+
+/// Returns the dir with the backup of the vanilla files for the given MAS directory
+pub fn get_vanilla_backup_dir(mas_dir: &Path) -> PathBuf {
+    return mas_dir.join(BACKUP_DIR_NAME).join(VANILLA_DIR_NAME);
+}
+
+/// Checks if the given path (relative to the MAS dir) is one of the files vanilla DDLC ships
+fn is_vanilla_path(path: &str) -> bool {
+    return VANILLA_FILES.contains(&path)
+        || VANILLA_DIRS.iter().any(|dir| path.strip_prefix(dir).map_or(false, |rest| rest.starts_with('/')));
+}
+
+/// Returns the paths of the entries we can remove from the dir: the file is still the one
+/// we installed or there's a vanilla backup to put back, anything else isn't ours to remove
+/// Vanilla files are only removed if we have the backup, the dir without it (spritepacks) has none
+fn get_removable_files<'a>(
+    dir: &Path,
+    vanilla_backup_dir: Option<&Path>,
+    entries: impl Iterator<Item = &'a ManifestEntry>
+) -> Vec<String> {
+    return entries
+        .filter(|entry| {
+            if let Some(vanilla_backup_dir) = vanilla_backup_dir {
+                if vanilla_backup_dir.join(&entry.path).is_file() {
+                    return true;
+                }
+                if is_vanilla_path(&entry.path) {
+                    return false;
+                }
+            }
+            return installer::is_same_file(&dir.join(&entry.path), entry.size, entry.crc32);
+        })
+        .map(|entry| entry.path.clone())
+        .collect();
+}
+
+
+/// Returns the files of the installation, the MAS ones and the spritepacks
+/// Uses the manifest if we have it, otherwise the file list of the release archive
+fn get_installed_files<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    mas_dir: &Path
+) -> Result<(Vec<String>, Vec<String>), InstallError> {
+    let vanilla_backup_dir = get_vanilla_backup_dir(mas_dir);
+
+    match Manifest::load(&manifest::get_manifest_path(mas_dir)) {
+        Ok(manifest) => {
+            let spr_dir = installer::get_spr_destination(app_state);
+            return Ok(
+                (
+                    get_removable_files(mas_dir, Some(&vanilla_backup_dir), manifest.get_mas_dir_files()),
+                    get_removable_files(&spr_dir, None, manifest.get_spritepacks_dir_files().iter())
+                )
+            );
+        },
+        Err(e) => eprintln!("Failed to read the install manifest: {e}, using the release archive")
+    };
+
+    let (local_mas_archive, tag) = {
+        let app_state = app_state.lock().unwrap();
+        (app_state.get_local_mas_archive().cloned(), app_state.get_release_tag().cloned())
+    };
+    let archive = match local_mas_archive {
+        Some(mas_archive) => File::open(mas_archive)?,
+        None => {
+            // The release the user selected takes priority
+            if tag.is_none() {
+                match installer::find_installed_tag(app_state, mas_dir)? {
+                    Some(tag) => {
+                        println!("Using the file list of release {tag}...");
+                        app_state.lock().unwrap().set_release_tag(Some(tag));
+                    },
+                    None => println!("Can't tell the installed version, using the latest release...")
+                };
+            }
+            // The deluxe version has all the files of the default one
+            installer::download_mas_archive(sender, app_state, Variant::Deluxe)?.0
+        }
+    };
+
+    let entries = installer::get_archive_entries(&archive)?;
+    let files = get_removable_files(mas_dir, Some(&vanilla_backup_dir), entries.iter());
+
+    // We don't know what spritepacks the user has, so leave them be
+    return Ok((files, Vec::new()));
+}
+
+/// Removes the given files from the dir, then the dirs they leave empty
+/// Returns false if aborted
+fn remove_files<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction,
+    dir: &Path,
+    files: &[String]
+) -> Result<bool, InstallError> {
+    sender.send(Message::UpdateProgressBar(0.0));
+
+    let mut parent_dirs = HashSet::new();
+    for (i, file) in files.iter().enumerate() {
+        let path = dir.join(file);
+        if path.is_file() {
+            transaction.remove_file(&path)?;
+        }
+        if let Some(parent_dir) = path.parent() {
+            if parent_dir != dir {
+                parent_dirs.insert(parent_dir.to_path_buf());
+            }
+        }
+
+        let pb_val = (i as f64 + 1.0) / files.len() as f64;
+        sender.send(Message::UpdateProgressBar(pb_val));
+
+        if app_state.lock().unwrap().get_abort_flag() {
+            return Ok(false);
+        }
+    }
+
+    // Deepest first, so the parents are empty by the time we get to them
+    let mut parent_dirs: Vec<PathBuf> = parent_dirs.into_iter().collect();
+    parent_dirs.sort_by_key(|d| Reverse(d.components().count()));
+    for parent_dir in parent_dirs {
+        let mut current = parent_dir.as_path();
+        while current != dir && current.starts_with(dir) {
+            let is_empty = fs::read_dir(current).map(|mut c| c.next().is_none()).unwrap_or(false);
+            if !is_empty {
+                break;
+            }
+            transaction.remove_dir_all(current)?;
+            current = match current.parent() {
+                Some(p) => p,
+                None => break
+            };
+        }
+    }
+
+    return Ok(true);
+}
+
+/// Puts the vanilla files from the backup back into the MAS dir, removes the backup
+fn restore_vanilla_files(transaction: &mut Transaction, mas_dir: &Path) -> Result<(), InstallError> {
+    let backup_dir = get_vanilla_backup_dir(mas_dir);
+    if !backup_dir.is_dir() {
+        return Ok(());
+    }
+
+    let mut dirs = vec![backup_dir.clone()];
+    while let Some(dir) = dirs.pop() {
+        for item in fs::read_dir(&dir)? {
+            let item_path = item?.path();
+            if item_path.is_dir() {
+                dirs.push(item_path);
+                continue;
+            }
+            // Can't fail, we're walking the backup dir
+            let rel_path = item_path.strip_prefix(&backup_dir).unwrap();
+            let target_path = mas_dir.join(rel_path);
+            if let Some(parent_dir) = target_path.parent() {
+                transaction.create_dir_all(parent_dir)?;
+            }
+            transaction.prepare_write(&target_path)?;
+            fs::copy(&item_path, &target_path)?;
+        }
+    }
+
+    transaction.remove_dir_all(&mas_dir.join(BACKUP_DIR_NAME))?;
+    return Ok(());
+}
+
+/// Removes MAS from the MAS dir and restores the vanilla files
+fn run_uninstall<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState,
+    transaction: &mut Transaction
+) -> InstallResult {
+    let mas_dir = installer::get_destination(app_state);
+    let (files, spritepack_files) = get_installed_files(sender, app_state, &mas_dir)?;
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
+
+    sender.send(Message::Uninstalling);
+    if !remove_files(sender, app_state, transaction, &mas_dir, &files)? {
+        return Ok(());
+    }
+    let spr_dir = installer::get_spr_destination(app_state);
+    if !remove_files(sender, app_state, transaction, &spr_dir, &spritepack_files)? {
+        return Ok(());
+    }
+
+    restore_vanilla_files(transaction, &mas_dir)?;
+
+    let manifest_path = manifest::get_manifest_path(&mas_dir);
+    if manifest_path.is_file() {
+        transaction.remove_file(&manifest_path)?;
+    }
+
+    transaction.commit();
+    sender.send(Message::Uninstalled);
+
+    return Ok(());
+}
+
+
+/// Main method to handle game uninstallation process
+/// Everything is done in a transaction, if the uninstallation doesn't finish,
+/// the removed files are restored
+pub fn uninstall_game<S: MessageSender>(
+    sender: &S,
+    app_state: &ThreadSafeState
+) -> InstallResult {
+    sender.send(Message::Preparing);
+    sender.send(Message::UpdateProgressBar(0.0));
+
+    if app_state.lock().unwrap().get_abort_flag() {
+        return Ok(());
+    }
+
+    let extraction_dir = app_state.lock().unwrap().get_extraction_dir().clone();
+    let mut transaction = Transaction::new(&extraction_dir)?;
+
+    let rv = run_uninstall(sender, app_state, &mut transaction);
+
+    // We didn't get to Uninstalled, either an error or an abort
+    if !transaction.is_committed() {
+        sender.send(Message::RollingBack);
+        let failed = transaction.rollback();
+        app_state.lock().unwrap().set_rollback_failed(Some(failed));
+        if failed != 0 {
+            eprintln!("Failed to restore {failed} file(s)");
+        }
+    }
+
+    return rv;
+}
+
+/// Threaded version of uninstall_game
+pub fn uninstall_game_in_thread(
+    sender: Sender<Message>,
+    app_state: &ThreadSafeState
+) -> thread::JoinHandle<InstallResult> {
+
+    let app_state = app_state.clone();
+
+    return thread::spawn(
+        move || -> InstallResult {
+            return match uninstall_game(&sender, &app_state) {
+                Err(e) => {
+                    sender.send(Message::Error);
+                    Err(e)
+                },
+                Ok(_) => Ok(())
+            };
+        }
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex}
+    };
+    use zip::{write::FileOptions, ZipWriter};
+    use crate::app::state::AppState;
+
+    struct NullSender;
+
+    impl MessageSender for NullSender {
+        fn send(&self, _msg: Message) {}
+    }
+
+    fn write_file(path: &Path, data: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    fn get_entry(path: &str, data: &[u8]) -> ManifestEntry {
+        return ManifestEntry {
+            path: path.to_string(),
+            size: data.len() as u64,
+            crc32: crc32fast::hash(data)
+        };
+    }
+
+    /// Creates an installation: an unchanged file, a file the user changed,
+    /// a replaced vanilla file with the backup and one without it
+    /// Returns the entries of the installed files
+    fn create_installation(mas_dir: &Path) -> Vec<ManifestEntry> {
+        write_file(&mas_dir.join("game/mod.rpyc"), b"mas");
+        write_file(&mas_dir.join("game/edited.rpyc"), b"edited by the user");
+        write_file(&mas_dir.join("renpy/common/00gui.rpy"), b"mas gui");
+        write_file(&get_vanilla_backup_dir(mas_dir).join("renpy/common/00gui.rpy"), b"vanilla gui");
+        write_file(&mas_dir.join("renpy/common/00keymap.rpy"), b"mas keymap");
+
+        return vec![
+            get_entry("game/mod.rpyc", b"mas"),
+            get_entry("game/edited.rpyc", b"mas"),
+            get_entry("renpy/common/00gui.rpy", b"mas gui"),
+            get_entry("renpy/common/00keymap.rpy", b"mas keymap")
+        ];
+    }
+
+    fn build_state(mas_dir: &Path) -> ThreadSafeState {
+        return Arc::new(Mutex::new(AppState::new(mas_dir.to_path_buf(), false, false, false, 0.0)));
+    }
+
+    fn assert_removable(mut files: Vec<String>) {
+        files.sort();
+        assert_eq!(files, vec!["game/mod.rpyc", "renpy/common/00gui.rpy"]);
+    }
+
+    #[test]
+    fn test_get_installed_files_from_manifest() {
+        let root = tempfile::tempdir().unwrap();
+        let entries = create_installation(root.path());
+        Manifest::new(Some("v0.12.15".to_string()), Variant::Default, entries, Vec::new(), false)
+            .save(&manifest::get_manifest_path(root.path()))
+            .unwrap();
+
+        let (files, spritepack_files) = get_installed_files(&NullSender, &build_state(root.path()), root.path()).unwrap();
+        assert_removable(files);
+        assert!(spritepack_files.is_empty());
+    }
+
+    #[test]
+    fn test_get_installed_files_from_archive() {
+        let root = tempfile::tempdir().unwrap();
+        let entries = create_installation(root.path());
+
+        let archives_dir = tempfile::tempdir().unwrap();
+        let archive_path = archives_dir.path().join("Monika_After_Story-0.12.15-Mod.zip");
+        let mut zip = ZipWriter::new(File::create(&archive_path).unwrap());
+        for (entry, data) in entries.iter().zip([&b"mas"[..], b"mas", b"mas gui", b"mas keymap"]) {
+            zip.start_file(entry.path.as_str(), FileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let app_state = build_state(root.path());
+        app_state.lock().unwrap().set_local_mas_archive(Some(archive_path));
+        let (files, spritepack_files) = get_installed_files(&NullSender, &app_state, root.path()).unwrap();
+        assert_removable(files);
+        assert!(spritepack_files.is_empty());
+    }
+}
@@ -2,7 +2,8 @@
 
 use std::{
     env,
-    path::PathBuf,
+    io,
+    path::{Path, PathBuf},
     fs::read_dir
 };
 
@@ -11,6 +12,12 @@ use fltk::{
     enums::Event
 };
 
+use crate::{
+    compat::{self, Incompatibility},
+    installer::DownloadStats,
+    version
+};
+
 
 /// Disables global hotkeys by consuming all shortcut events
 pub fn disable_global_hotkeys() {
@@ -25,76 +32,307 @@ pub fn disable_global_hotkeys() {
 }
 
 
+/// Attaches to the console of the parent process, so the output of the CLI is visible,
+/// release builds are GUI apps on Windows and don't get a console of their own
+#[cfg(windows)]
+pub fn attach_parent_console() {
+    use windows_sys::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+
+    // SAFETY: no pointers involved, fails if there's no parent console, that's fine
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Attaches to the console of the parent process, only needed on Windows
+#[cfg(not(windows))]
+pub fn attach_parent_console() {}
+
+
 /// Returns current working dir
 pub fn get_cwd() -> PathBuf {
     let cwd = env::current_dir();
     return cwd.ok().unwrap_or_default();
 }
 
-/// Checks if the given path is a valid DDLC directory
-pub fn is_valid_ddlc_dir(path: &PathBuf) -> bool {
-    const TOTAL_CONDITIONS: u16 = 5;
-    const REQUIRED_FLAG: u16 = 2 << TOTAL_CONDITIONS;
+/// Returns the dir where the installer keeps its cache,
+/// falls back to the temp dir if the platform one is unknown
+pub fn get_cache_dir() -> PathBuf {
+    let base_dir = match env::consts::OS {
+        "windows" => env::var_os("LOCALAPPDATA").map(PathBuf::from),
+        "macos" => env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches")),
+        _ => {
+            env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                // The spec says relative paths must be ignored
+                .filter(|path| path.is_absolute())
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        }
+    };
+    return base_dir.unwrap_or_else(env::temp_dir).join("mas-installer");
+}
 
-    if !path.is_dir() {
-        return false;
+/// Returns the dir where the installer keeps its data (e.g. backups),
+/// unlike the cache, it's not supposed to be cleaned up
+pub fn get_data_dir() -> PathBuf {
+    let base_dir = match env::consts::OS {
+        "windows" => env::var_os("APPDATA").map(PathBuf::from),
+        "macos" => env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support")),
+        _ => {
+            env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                // The spec says relative paths must be ignored
+                .filter(|path| path.is_absolute())
+                .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        }
+    };
+    return base_dir.unwrap_or_else(get_cwd).join("mas-installer");
+}
+
+/// Returns the space available to us on the filesystem of the given path,
+/// the path doesn't have to exist, the closest existing parent is used
+pub fn get_available_space(path: &Path) -> Result<u64, io::Error> {
+    let path = path.ancestors()
+        .find(|p| p.exists())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no existing parent dir"))?;
+    return _get_available_space(path);
+}
+
+#[cfg(unix)]
+fn _get_available_space(path: &Path) -> Result<u64, io::Error> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stats = MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: the path is a valid C string and statvfs fills the struct on success
+    let stats = unsafe {
+        if libc::statvfs(c_path.as_ptr(), stats.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        stats.assume_init()
+    };
+    // The field types differ between the platforms
+    return Ok(stats.f_bavail as u64 * stats.f_frsize as u64);
+}
+
+#[cfg(windows)]
+fn _get_available_space(path: &Path) -> Result<u64, io::Error> {
+    use std::{os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let w_path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available: u64 = 0;
+    // SAFETY: the path is null-terminated and the out pointer is valid
+    let rv = unsafe {
+        GetDiskFreeSpaceExW(w_path.as_ptr(), &mut available, ptr::null_mut(), ptr::null_mut())
+    };
+    if rv == 0 {
+        return Err(io::Error::last_os_error());
     }
+    return Ok(available);
+}
+
+/// Formats the given unix time (UTC), e.g. 20221231-235959
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    // Converts days since the epoch to a civil date
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365;
+    let doy = doe - (365*yoe + yoe/4 - yoe/100);
+    let mp = (5*doy + 2) / 153;
+    let day = doy - (153*mp + 2)/5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era*400 + if month <= 2 { 1 } else { 0 };
+
+    return format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    );
+}
+
+/// Formats the given number of bytes for humans, e.g. 1.5 MiB
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
 
-    if env::consts::OS == "macos" {
-        if let Some(last_dir) = path.file_name() {
-            let last_dir = last_dir.to_os_string().into_string();
-            if last_dir.is_err() {
-                return true;
-            }
-            let last_dir = last_dir.unwrap();
-            let last_dir_str = last_dir.as_str();
-            return last_dir_str == "DDLC.app";
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in UNITS.iter().skip(1) {
+        if size < 1024.0 {
+            break;
         }
+        size /= 1024.0;
+        unit = next_unit;
     }
+    return format!("{size:.1} {unit}");
+}
 
-    let content = read_dir(path);
-    if content.is_err() {
-        eprintln!("Failed to read content of the selected folder");
-        // If we failed to read, we allow to install anyway - the folder might be valid
-        return true;
+/// Formats the given number of seconds for humans, e.g. 2m 05s
+pub fn format_duration(secs: u64) -> String {
+    return match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+        _ => format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    };
+}
+
+/// Formats the download stats for humans, e.g. 1.5 MiB of 3.0 MiB, 512.0 KiB/s, 3s left
+pub fn format_download_stats(stats: &DownloadStats) -> String {
+    let mut rv = format!("{} of {}", format_size(stats.downloaded), format_size(stats.total));
+    if stats.rate > 0.0 {
+        rv += &format!(", {}/s", format_size(stats.rate as u64));
+    }
+    if let Some(eta) = stats.eta {
+        rv += &format!(", {} left", format_duration(eta));
     }
+    return rv;
+}
 
-    let content = content.unwrap();
-    let mut flag: u16 = 2;
-    for item in content {
-        if item.is_err() {
-            eprintln!("Failed to read content of the selected folder");
-            return true;
-        }
+/// The dirs every DDLC installation has
+const DDLC_DIRS: [&str; 3] = ["characters", "game", "renpy"];
+/// The files every DDLC installation has
+const DDLC_FILES: [&str; 2] = ["DDLC.py", "DDLC.sh"];
+/// The name of the app bundle on macOS
+const DDLC_MACOS_BUNDLE: &str = "DDLC.app";
 
-        let item = item.unwrap();
-        let file_name = item.file_name().into_string();
-        // It should be valid utf-8, otherwise it's unlikely to be a DDLC file and we can skip
-        if file_name.is_err() {
-            continue;
-        }
-        let file_name = file_name.unwrap();
-        let file_name_str = file_name.as_str();
-
-        // Increase flag for each condition met
-        // start from 2 and 5 conditions, means 2^6
-        if item.path().is_dir() {
-            match file_name_str {
-                "characters" | "game" | "renpy" => {flag *= 2;},
-                _ => {},
-            };
-        }
-        else {
-            match file_name_str {
-                "DDLC.py" | "DDLC.sh" => {flag *= 2;},
-                _ => {},
-            };
-        }
 
-        if flag == REQUIRED_FLAG {
+/// The DDLC files we found in a directory
+#[derive(Debug)]
+pub struct DdlcDirLayout {
+    /// The dirs and files DDLC has with whether we found them
+    pub items: Vec<(&'static str, bool)>,
+    /// Whether we could read the content of the dir
+    pub is_readable: bool,
+    /// Whether the dir is the DDLC app bundle (macOS)
+    pub is_macos_bundle: bool,
+    /// The dir with the game files, it's inside the bundle on macOS
+    pub game_dir: PathBuf
+}
+
+impl DdlcDirLayout {
+    /// Returns the items we didn't find
+    pub fn get_missing_items(&self) -> impl Iterator<Item = &'static str> + '_ {
+        return self.items.iter()
+            .filter(|(_, found)| !found)
+            .map(|(name, _)| *name);
+    }
+
+    /// Checks if the dir looks like DDLC
+    /// If we failed to read it, we allow to install anyway - the dir might be valid
+    pub fn is_valid(&self) -> bool {
+        // We install into the bundle on macOS, a plain dir won't do
+        if env::consts::OS == "macos" {
+            return self.is_macos_bundle;
+        }
+        if !self.is_readable {
             return true;
         }
+        return self.get_missing_items().next().is_none();
+    }
+}
+
+/// The result of checking the selected DDLC directory
+#[derive(Debug)]
+pub struct DdlcDirReport {
+    /// The DDLC files we found
+    pub layout: DdlcDirLayout,
+    /// Whether we can write into the dir
+    pub is_writable: bool,
+    /// The version of MAS if it's installed
+    pub mas_version: Option<String>,
+    /// Why MAS can't be installed here, e.g. it's DDLC Plus
+    pub incompatibility: Option<Incompatibility>
+}
+
+/// Looks for the DDLC files in the given directory
+/// Only reads the dir, so it's fine for the dirs the user didn't select
+pub fn check_ddlc_dir_layout(path: &Path) -> DdlcDirLayout {
+    let is_macos_bundle = env::consts::OS == "macos"
+        && path.is_dir()
+        && path.file_name().map_or(false, |name| name == DDLC_MACOS_BUNDLE);
+    // The game is inside the bundle on macOS
+    let game_dir = match is_macos_bundle {
+        true => path.join("Contents/Resources/autorun"),
+        false => path.to_path_buf()
+    };
+
+    let is_readable = read_dir(&game_dir).is_ok();
+    let items = DDLC_DIRS.iter()
+        .map(|name| (*name, game_dir.join(name).is_dir()))
+        .chain(DDLC_FILES.iter().map(|name| (*name, game_dir.join(name).is_file())))
+        .collect();
+
+    return DdlcDirLayout {
+        items,
+        is_readable,
+        is_macos_bundle,
+        game_dir
+    };
+}
+
+/// Checks the DDLC directory the user selected, see DdlcDirReport
+/// Creates a temp file to tell if we can write into it, use check_ddlc_dir_layout for other dirs
+pub fn check_ddlc_dir(path: &Path) -> DdlcDirReport {
+    let layout = check_ddlc_dir_layout(path);
+    // The file is removed right away
+    let is_writable = tempfile::tempfile_in(&layout.game_dir).is_ok();
+    let (mas_version, incompatibility) = match layout.is_readable {
+        true => {
+            let mas_version = version::get_installed_version(&layout.game_dir);
+            let incompatibility = compat::check_compatibility(&layout.game_dir, mas_version.is_some());
+            (mas_version, incompatibility)
+        },
+        false => (None, None)
+    };
+
+    return DdlcDirReport {
+        layout,
+        is_writable,
+        mas_version,
+        incompatibility
+    };
+}
+
+/// Checks if the given path is a valid DDLC directory, only reads the dir
+pub fn is_valid_ddlc_dir(path: &PathBuf) -> bool {
+    if !path.is_dir() {
+        return false;
+    }
+    let layout = check_ddlc_dir_layout(path);
+    if !layout.is_readable {
+        eprintln!("Failed to read content of the selected folder");
     }
+    return layout.is_valid();
+}
+
 
-    return flag == REQUIRED_FLAG;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "19700101-000000");
+        assert_eq!(format_timestamp(1672531199), "20221231-235959");
+    }
+
+    #[test]
+    fn test_format_timestamp_leap_day() {
+        assert_eq!(format_timestamp(1709210096), "20240229-123456");
+        assert_eq!(format_timestamp(1709251200), "20240301-000000");
+        assert_eq!(format_timestamp(951782400), "20000229-000000");
+    }
 }
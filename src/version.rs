@@ -0,0 +1,108 @@
+/// The module that implements detection and comparison of MAS versions
+
+use std::{
+    cmp::Ordering,
+    fs::{File, read_to_string},
+    path::Path
+};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::manifest::{self, Manifest};
+
+
+lazy_static! {
+    static ref CONFIG_VERSION_RE: Regex = Regex::new(r#"config\.version\s*=\s*"([^"]+)""#).unwrap();
+}
+
+
+/// Parses a version (or a tag) like v0.12.15 into its numbers
+/// Suffixes (e.g. -rc1) are ignored, returns None if there are no numbers
+pub fn parse_version(version: &str) -> Option<Vec<u32>> {
+    let version = version.trim().trim_start_matches(|c| c == 'v' || c == 'V');
+    let version = version.split(|c| c == '-' || c == '+' || c == ' ').next()?;
+
+    let parts: Option<Vec<u32>> = version.split('.').map(|part| part.parse::<u32>().ok()).collect();
+    return parts.filter(|parts| !parts.is_empty());
+}
+
+/// Compares two versions, None if any of them can't be parsed
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let mut a = parse_version(a)?;
+    let mut b = parse_version(b)?;
+    // 0.12 is the same as 0.12.0
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    return Some(a.cmp(&b));
+}
+
+
+/// Reads the version from config.version in the options script, only if it's not compiled
+fn get_version_from_options(mas_dir: &Path) -> Option<String> {
+    let content = read_to_string(mas_dir.join("game/options.rpy")).ok()?;
+    let captures = CONFIG_VERSION_RE.captures(&content)?;
+    return Some(captures.get(1)?.as_str().to_string());
+}
+
+/// Reads the version from the Ren'Py updater data MAS ships
+fn get_version_from_update_data(mas_dir: &Path) -> Option<String> {
+    let file = File::open(mas_dir.join("game/update/current.json")).ok()?;
+    let data: serde_json::Value = serde_json::from_reader(file).ok()?;
+    // The format is {"<name>": {"version": "<version>", ...}}
+    return data.as_object()?
+        .values()
+        .find_map(|v| v.get("version")?.as_str())
+        .map(str::to_string);
+}
+
+/// Returns the version of MAS installed in the given dir, None if it's not installed
+/// or we can't tell the version
+pub fn get_installed_version(mas_dir: &Path) -> Option<String> {
+    if let Ok(manifest) = Manifest::load(&manifest::get_manifest_path(mas_dir)) {
+        if manifest.tag.is_some() {
+            return manifest.tag;
+        }
+    }
+    return get_version_from_options(mas_dir).or_else(|| get_version_from_update_data(mas_dir));
+}
+
+/// Returns the tag of the given version from the list, e.g. v0.12.15 for 0.12.15
+pub fn find_tag<'a>(tags: &'a [String], version: &str) -> Option<&'a String> {
+    return tags.iter().find(|tag| compare_versions(tag, version) == Some(Ordering::Equal));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("0.12.15"), Some(vec![0, 12, 15]));
+        assert_eq!(parse_version("v0.12.15"), Some(vec![0, 12, 15]));
+        assert_eq!(parse_version("V0.12"), Some(vec![0, 12]));
+        assert_eq!(parse_version("v0.12.15-rc1"), Some(vec![0, 12, 15]));
+        assert_eq!(parse_version(" 0.12.15 "), Some(vec![0, 12, 15]));
+        assert_eq!(parse_version("v"), None);
+        assert_eq!(parse_version("latest"), None);
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("v0.12.15", "0.12.15"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("0.12", "v0.12.0"), Some(Ordering::Equal));
+        assert_eq!(compare_versions("v0.12.15", "0.12.9"), Some(Ordering::Greater));
+        assert_eq!(compare_versions("0.11.10", "v0.12.0"), Some(Ordering::Less));
+        assert_eq!(compare_versions("v0.12.15", "unknown"), None);
+    }
+
+    #[test]
+    fn test_find_tag() {
+        let tags = vec!["v0.12.15".to_string(), "v0.12.14".to_string()];
+        assert_eq!(find_tag(&tags, "0.12.14"), Some(&tags[1]));
+        assert_eq!(find_tag(&tags, "v0.12.15"), Some(&tags[0]));
+        assert_eq!(find_tag(&tags, "0.12.13"), None);
+    }
+}